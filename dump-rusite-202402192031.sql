@@ -1,113 +0,0 @@
--- MariaDB dump 10.19-11.2.3-MariaDB, for Linux (x86_64)
---
--- Host: 124.223.209.159    Database: rusite
--- ------------------------------------------------------
--- Server version	10.11.6-MariaDB-0+deb12u1
-
-/*!40101 SET @OLD_CHARACTER_SET_CLIENT=@@CHARACTER_SET_CLIENT */;
-/*!40101 SET @OLD_CHARACTER_SET_RESULTS=@@CHARACTER_SET_RESULTS */;
-/*!40101 SET @OLD_COLLATION_CONNECTION=@@COLLATION_CONNECTION */;
-/*!40101 SET NAMES utf8mb4 */;
-/*!40103 SET @OLD_TIME_ZONE=@@TIME_ZONE */;
-/*!40103 SET TIME_ZONE='+00:00' */;
-/*!40014 SET @OLD_UNIQUE_CHECKS=@@UNIQUE_CHECKS, UNIQUE_CHECKS=0 */;
-/*!40014 SET @OLD_FOREIGN_KEY_CHECKS=@@FOREIGN_KEY_CHECKS, FOREIGN_KEY_CHECKS=0 */;
-/*!40101 SET @OLD_SQL_MODE=@@SQL_MODE, SQL_MODE='NO_AUTO_VALUE_ON_ZERO' */;
-/*!40111 SET @OLD_SQL_NOTES=@@SQL_NOTES, SQL_NOTES=0 */;
-
---
--- Table structure for table `category_set`
---
-
-DROP TABLE IF EXISTS `category_set`;
-/*!40101 SET @saved_cs_client     = @@character_set_client */;
-/*!40101 SET character_set_client = utf8 */;
-CREATE TABLE `category_set` (
-  `id` int(10) unsigned NOT NULL AUTO_INCREMENT,
-  `category_name` varchar(255) NOT NULL,
-  PRIMARY KEY (`id`),
-  UNIQUE KEY `category_set_unique` (`category_name`)
-) ENGINE=InnoDB AUTO_INCREMENT=6 DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_general_ci;
-/*!40101 SET character_set_client = @saved_cs_client */;
-
---
--- Table structure for table `essay_category`
---
-
-DROP TABLE IF EXISTS `essay_category`;
-/*!40101 SET @saved_cs_client     = @@character_set_client */;
-/*!40101 SET character_set_client = utf8 */;
-CREATE TABLE `essay_category` (
-  `eid` uuid NOT NULL,
-  `category_id` int(10) unsigned NOT NULL,
-  PRIMARY KEY (`category_id`,`eid`),
-  KEY `essay_category_essays_FK` (`eid`),
-  CONSTRAINT `essay_category_category_set_FK` FOREIGN KEY (`category_id`) REFERENCES `category_set` (`id`) ON DELETE CASCADE ON UPDATE CASCADE,
-  CONSTRAINT `essay_category_essays_FK` FOREIGN KEY (`eid`) REFERENCES `essays` (`eid`) ON DELETE CASCADE ON UPDATE CASCADE
-) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_general_ci;
-/*!40101 SET character_set_client = @saved_cs_client */;
-
---
--- Table structure for table `essay_tag`
---
-
-DROP TABLE IF EXISTS `essay_tag`;
-/*!40101 SET @saved_cs_client     = @@character_set_client */;
-/*!40101 SET character_set_client = utf8 */;
-CREATE TABLE `essay_tag` (
-  `eid` uuid NOT NULL,
-  `tag_id` int(10) unsigned NOT NULL,
-  PRIMARY KEY (`eid`,`tag_id`),
-  KEY `essay_tag_tag_set_FK` (`tag_id`),
-  CONSTRAINT `essay_tag_essays_FK` FOREIGN KEY (`eid`) REFERENCES `essays` (`eid`) ON DELETE CASCADE ON UPDATE CASCADE,
-  CONSTRAINT `essay_tag_tag_set_FK` FOREIGN KEY (`tag_id`) REFERENCES `tag_set` (`id`) ON DELETE CASCADE ON UPDATE CASCADE
-) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_general_ci;
-/*!40101 SET character_set_client = @saved_cs_client */;
-
---
--- Table structure for table `essays`
---
-
-DROP TABLE IF EXISTS `essays`;
-/*!40101 SET @saved_cs_client     = @@character_set_client */;
-/*!40101 SET character_set_client = utf8 */;
-CREATE TABLE `essays` (
-  `eid` uuid NOT NULL DEFAULT uuid(),
-  `title` varchar(255) NOT NULL,
-  `date` datetime DEFAULT NULL,
-  `brief` text NOT NULL DEFAULT 'None',
-  `content` longtext DEFAULT NULL,
-  `last_save_time` double NOT NULL DEFAULT 0,
-  PRIMARY KEY (`eid`)
-) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_general_ci;
-/*!40101 SET character_set_client = @saved_cs_client */;
-
---
--- Table structure for table `tag_set`
---
-
-DROP TABLE IF EXISTS `tag_set`;
-/*!40101 SET @saved_cs_client     = @@character_set_client */;
-/*!40101 SET character_set_client = utf8 */;
-CREATE TABLE `tag_set` (
-  `id` int(10) unsigned NOT NULL AUTO_INCREMENT,
-  `tag_name` varchar(255) NOT NULL,
-  PRIMARY KEY (`id`),
-  UNIQUE KEY `tag_set_unique` (`tag_name`)
-) ENGINE=InnoDB AUTO_INCREMENT=18 DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_general_ci;
-/*!40101 SET character_set_client = @saved_cs_client */;
-
---
--- Dumping routines for database 'rusite'
---
-/*!40103 SET TIME_ZONE=@OLD_TIME_ZONE */;
-
-/*!40101 SET SQL_MODE=@OLD_SQL_MODE */;
-/*!40014 SET FOREIGN_KEY_CHECKS=@OLD_FOREIGN_KEY_CHECKS */;
-/*!40014 SET UNIQUE_CHECKS=@OLD_UNIQUE_CHECKS */;
-/*!40101 SET CHARACTER_SET_CLIENT=@OLD_CHARACTER_SET_CLIENT */;
-/*!40101 SET CHARACTER_SET_RESULTS=@OLD_CHARACTER_SET_RESULTS */;
-/*!40101 SET COLLATION_CONNECTION=@OLD_COLLATION_CONNECTION */;
-/*!40111 SET SQL_NOTES=@OLD_SQL_NOTES */;
-
--- Dump completed on 2024-02-19 20:31:51
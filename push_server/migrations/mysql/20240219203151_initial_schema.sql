@@ -0,0 +1,44 @@
+-- 最初的 schema，来自 dump-rusite-202402192031.sql。
+-- 使用 IF NOT EXISTS，已经由 dump 建好的数据库可以直接接入迁移
+
+CREATE TABLE IF NOT EXISTS `category_set` (
+  `id` int(10) unsigned NOT NULL AUTO_INCREMENT,
+  `category_name` varchar(255) NOT NULL,
+  PRIMARY KEY (`id`),
+  UNIQUE KEY `category_set_unique` (`category_name`)
+) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_general_ci;
+
+CREATE TABLE IF NOT EXISTS `tag_set` (
+  `id` int(10) unsigned NOT NULL AUTO_INCREMENT,
+  `tag_name` varchar(255) NOT NULL,
+  PRIMARY KEY (`id`),
+  UNIQUE KEY `tag_set_unique` (`tag_name`)
+) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_general_ci;
+
+CREATE TABLE IF NOT EXISTS `essays` (
+  `eid` uuid NOT NULL DEFAULT uuid(),
+  `title` varchar(255) NOT NULL,
+  `date` datetime DEFAULT NULL,
+  `brief` text NOT NULL DEFAULT 'None',
+  `content` longtext DEFAULT NULL,
+  `last_save_time` double NOT NULL DEFAULT 0,
+  PRIMARY KEY (`eid`)
+) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_general_ci;
+
+CREATE TABLE IF NOT EXISTS `essay_category` (
+  `eid` uuid NOT NULL,
+  `category_id` int(10) unsigned NOT NULL,
+  PRIMARY KEY (`category_id`,`eid`),
+  KEY `essay_category_essays_FK` (`eid`),
+  CONSTRAINT `essay_category_category_set_FK` FOREIGN KEY (`category_id`) REFERENCES `category_set` (`id`) ON DELETE CASCADE ON UPDATE CASCADE,
+  CONSTRAINT `essay_category_essays_FK` FOREIGN KEY (`eid`) REFERENCES `essays` (`eid`) ON DELETE CASCADE ON UPDATE CASCADE
+) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_general_ci;
+
+CREATE TABLE IF NOT EXISTS `essay_tag` (
+  `eid` uuid NOT NULL,
+  `tag_id` int(10) unsigned NOT NULL,
+  PRIMARY KEY (`eid`,`tag_id`),
+  KEY `essay_tag_tag_set_FK` (`tag_id`),
+  CONSTRAINT `essay_tag_essays_FK` FOREIGN KEY (`eid`) REFERENCES `essays` (`eid`) ON DELETE CASCADE ON UPDATE CASCADE,
+  CONSTRAINT `essay_tag_tag_set_FK` FOREIGN KEY (`tag_id`) REFERENCES `tag_set` (`id`) ON DELETE CASCADE ON UPDATE CASCADE
+) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_general_ci;
@@ -0,0 +1,10 @@
+-- 全文搜索的索引词
+
+CREATE TABLE IF NOT EXISTS `essay_term` (
+  `eid` uuid NOT NULL,
+  `term` varchar(255) NOT NULL,
+  `weight` double NOT NULL DEFAULT 0,
+  PRIMARY KEY (`eid`,`term`),
+  KEY `essay_term_term_IDX` (`term`),
+  CONSTRAINT `essay_term_essays_FK` FOREIGN KEY (`eid`) REFERENCES `essays` (`eid`) ON DELETE CASCADE ON UPDATE CASCADE
+) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_general_ci;
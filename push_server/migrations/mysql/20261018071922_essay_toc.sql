@@ -0,0 +1,3 @@
+-- 由标题生成的目录，json
+
+ALTER TABLE `essays` ADD COLUMN IF NOT EXISTS `toc` longtext DEFAULT NULL AFTER `content`;
@@ -0,0 +1,3 @@
+-- 源文件的 sha256，push_server 用来判断文章是否需要更新
+
+ALTER TABLE `essays` ADD COLUMN IF NOT EXISTS `content_hash` char(64) DEFAULT NULL AFTER `toc`;
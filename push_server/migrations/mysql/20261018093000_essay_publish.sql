@@ -0,0 +1,4 @@
+-- 草稿和定时发布，公开接口只返回 draft = 0 且 publish_at 为空或已经过去的文章
+
+ALTER TABLE `essays` ADD COLUMN IF NOT EXISTS `draft` tinyint(1) NOT NULL DEFAULT 0 AFTER `content_hash`;
+ALTER TABLE `essays` ADD COLUMN IF NOT EXISTS `publish_at` datetime DEFAULT NULL AFTER `draft`;
@@ -0,0 +1,16 @@
+-- 文章被更新前的版本，push_server 每次更新文章时把旧的一行连同 tags 和 categories (json 数组) 存进来
+
+CREATE TABLE IF NOT EXISTS `essay_revision` (
+  `eid` uuid NOT NULL,
+  `revision` int(10) unsigned NOT NULL,
+  `title` varchar(255) NOT NULL,
+  `date` datetime DEFAULT NULL,
+  `brief` text NOT NULL DEFAULT 'None',
+  `content` longtext DEFAULT NULL,
+  `categories` text NOT NULL DEFAULT '[]',
+  `tags` text NOT NULL DEFAULT '[]',
+  `saved_at` double NOT NULL DEFAULT 0,
+  `archived_at` double NOT NULL DEFAULT 0,
+  PRIMARY KEY (`eid`,`revision`),
+  CONSTRAINT `essay_revision_essays_FK` FOREIGN KEY (`eid`) REFERENCES `essays` (`eid`) ON DELETE CASCADE ON UPDATE CASCADE
+) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_general_ci;
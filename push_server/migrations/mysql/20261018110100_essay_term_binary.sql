@@ -0,0 +1,4 @@
+-- 索引词已经由 search::tokenize 转为小写，term 按字节比较。
+-- 不区分大小写和重音的 utf8mb4_general_ci 会把 `cafe` 和 `café` 当成同一个主键，同步时写入失败
+
+ALTER TABLE `essay_term` MODIFY `term` varchar(255) COLLATE utf8mb4_bin NOT NULL;
@@ -0,0 +1,4 @@
+-- 历史版本中保存的字段的 hash (见 Essay::version_hash)，更新文章时这些字段都没有变化就不存历史版本。
+-- 这个迁移之前写入的文章为 NULL，下次更新时总是存历史版本
+
+ALTER TABLE `essays` ADD COLUMN IF NOT EXISTS `version_hash` char(64) DEFAULT NULL AFTER `content_hash`;
@@ -0,0 +1,4 @@
+-- 历史版本也记录草稿和定时发布，从来没有公开过的版本不出现在公开接口中
+
+ALTER TABLE `essay_revision` ADD COLUMN IF NOT EXISTS `draft` tinyint(1) NOT NULL DEFAULT 0 AFTER `brief`;
+ALTER TABLE `essay_revision` ADD COLUMN IF NOT EXISTS `publish_at` datetime DEFAULT NULL AFTER `draft`;
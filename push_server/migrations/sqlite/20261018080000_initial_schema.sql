@@ -0,0 +1,48 @@
+-- SQLite 的完整 schema，和 MySQL 迁移之后的结果对应。
+-- tag 和 category 的名字列使用 NOCASE，不区分大小写。NOCASE 只折叠 ASCII 字母，
+-- 而 MySQL 的 utf8mb4_general_ci 还会折叠非 ASCII 字母的大小写和重音，
+-- 所以 `Café` 和 `cafe` 在 MySQL 中是同一个 tag，在 SQLite 中是两个。
+-- essay_term.term 和 MySQL 一样按字节比较 (SQLite 默认的 BINARY)
+
+CREATE TABLE IF NOT EXISTS category_set (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  category_name TEXT NOT NULL COLLATE NOCASE UNIQUE
+);
+
+CREATE TABLE IF NOT EXISTS tag_set (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  tag_name TEXT NOT NULL COLLATE NOCASE UNIQUE
+);
+
+CREATE TABLE IF NOT EXISTS essays (
+  eid TEXT NOT NULL PRIMARY KEY,
+  title TEXT NOT NULL,
+  date TEXT DEFAULT NULL,
+  brief TEXT NOT NULL DEFAULT 'None',
+  content TEXT DEFAULT NULL,
+  toc TEXT DEFAULT NULL,
+  content_hash TEXT DEFAULT NULL,
+  last_save_time REAL NOT NULL DEFAULT 0
+);
+
+CREATE TABLE IF NOT EXISTS essay_category (
+  eid TEXT NOT NULL REFERENCES essays (eid) ON DELETE CASCADE ON UPDATE CASCADE,
+  category_id INTEGER NOT NULL REFERENCES category_set (id) ON DELETE CASCADE ON UPDATE CASCADE,
+  PRIMARY KEY (category_id, eid)
+);
+CREATE INDEX IF NOT EXISTS essay_category_eid_IDX ON essay_category (eid);
+
+CREATE TABLE IF NOT EXISTS essay_tag (
+  eid TEXT NOT NULL REFERENCES essays (eid) ON DELETE CASCADE ON UPDATE CASCADE,
+  tag_id INTEGER NOT NULL REFERENCES tag_set (id) ON DELETE CASCADE ON UPDATE CASCADE,
+  PRIMARY KEY (eid, tag_id)
+);
+CREATE INDEX IF NOT EXISTS essay_tag_tag_id_IDX ON essay_tag (tag_id);
+
+CREATE TABLE IF NOT EXISTS essay_term (
+  eid TEXT NOT NULL REFERENCES essays (eid) ON DELETE CASCADE ON UPDATE CASCADE,
+  term TEXT NOT NULL,
+  weight REAL NOT NULL DEFAULT 0,
+  PRIMARY KEY (eid, term)
+);
+CREATE INDEX IF NOT EXISTS essay_term_term_IDX ON essay_term (term);
@@ -0,0 +1,4 @@
+-- 草稿和定时发布，publish_at 和 date 一样是 `YYYY-MM-DD HH:MM:SS` 格式的文本，可以直接比较
+
+ALTER TABLE essays ADD COLUMN draft INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE essays ADD COLUMN publish_at TEXT DEFAULT NULL;
@@ -0,0 +1,15 @@
+-- 文章被更新前的版本，tags 和 categories 保存为 json 数组
+
+CREATE TABLE IF NOT EXISTS essay_revision (
+  eid TEXT NOT NULL REFERENCES essays (eid) ON DELETE CASCADE ON UPDATE CASCADE,
+  revision INTEGER NOT NULL,
+  title TEXT NOT NULL,
+  date TEXT DEFAULT NULL,
+  brief TEXT NOT NULL DEFAULT 'None',
+  content TEXT DEFAULT NULL,
+  categories TEXT NOT NULL DEFAULT '[]',
+  tags TEXT NOT NULL DEFAULT '[]',
+  saved_at REAL NOT NULL DEFAULT 0,
+  archived_at REAL NOT NULL DEFAULT 0,
+  PRIMARY KEY (eid, revision)
+);
@@ -0,0 +1,8 @@
+-- SQLite 没有命名锁，push_server 同步时在这张表中插入一行作为同步锁，结束时删除。
+-- holder 是持有者随机生成的标识，只有持有者能删除自己的锁
+
+CREATE TABLE IF NOT EXISTS sync_lock (
+  name TEXT NOT NULL PRIMARY KEY,
+  holder TEXT NOT NULL,
+  locked_at REAL NOT NULL
+);
@@ -0,0 +1,3 @@
+-- 历史版本中保存的字段的 hash (见 Essay::version_hash)，更新文章时这些字段都没有变化就不存历史版本
+
+ALTER TABLE essays ADD COLUMN version_hash TEXT DEFAULT NULL;
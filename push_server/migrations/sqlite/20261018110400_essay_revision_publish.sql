@@ -0,0 +1,4 @@
+-- 历史版本也记录草稿和定时发布，从来没有公开过的版本不出现在公开接口中
+
+ALTER TABLE essay_revision ADD COLUMN draft INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE essay_revision ADD COLUMN publish_at TEXT DEFAULT NULL;
@@ -0,0 +1,237 @@
+use std::{collections::HashMap, fmt};
+
+use chrono::NaiveDateTime;
+use serde_json::Value;
+use tokio::fs;
+
+use crate::{
+    data_struct::{EssayInfo, MarkdownRenderer},
+    front_matter::{FrontMatter, FrontMatterError},
+    time::{normalize_datetime, DATE_FORMAT},
+};
+
+/// front matter 中必须有的字段，`eid` 可以省略，同步时自动生成
+pub const REQUIRED_FIELDS: [&str; 5] = ["title", "date", "categories", "tags", "brief"];
+
+/// 检查发现的一个问题，`line` 和 `column` 从 1 开始
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub path: String,
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+    pub message: String,
+}
+
+impl Diagnostic {
+    fn new(path: &str, line: Option<usize>, message: String) -> Self {
+        Self { path: path.to_string(), line, column: None, message }
+    }
+
+    fn front_matter(path: &str, err: FrontMatterError) -> Self {
+        Self {
+            path: path.to_string(),
+            line: err.line,
+            column: err.column,
+            message: format!("invalid front matter: {}", err.message),
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.path)?;
+        if let Some(line) = self.line {
+            write!(f, ":{line}")?;
+            if let Some(column) = self.column {
+                write!(f, ":{column}")?;
+            }
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+/// 检查所有文章，返回发现的全部问题，不会因为某一篇出错而中断
+pub async fn check_essays(paths: &[String], renderer: &MarkdownRenderer) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    // eid → 第一次出现的文件
+    let mut eids = HashMap::new();
+    for path in paths {
+        match fs::read_to_string(path).await {
+            Ok(content) => check_essay(path, &content, renderer, &mut eids, &mut diagnostics).await,
+            Err(err) => diagnostics.push(Diagnostic::new(path, None, format!("cannot read file: {err}"))),
+        }
+    }
+    diagnostics
+}
+
+async fn check_essay(
+    path: &str,
+    content: &str,
+    renderer: &MarkdownRenderer,
+    eids: &mut HashMap<String, String>,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    let front_matter = match FrontMatter::split(content) {
+        Ok(front_matter) => front_matter,
+        Err(err) => return diagnostics.push(Diagnostic::front_matter(path, err)),
+    };
+
+    check_front_matter(path, &front_matter, eids, diagnostics);
+
+    match renderer.render(front_matter.body).await {
+        Ok(rendered) => diagnostics.extend(rendered.warnings.into_iter().map(|warning| {
+            Diagnostic::new(path, Some(warning.line + front_matter.body_offset), warning.message)
+        })),
+        Err(err) => diagnostics.push(Diagnostic::new(path, None, format!("cannot render: {err}"))),
+    }
+}
+
+fn check_front_matter(
+    path: &str,
+    front_matter: &FrontMatter,
+    eids: &mut HashMap<String, String>,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    // 先解析成任意的值，一次报告所有缺少的字段
+    let value: Value = match front_matter.parse() {
+        Ok(value) => value,
+        Err(err) => return diagnostics.push(Diagnostic::front_matter(path, err)),
+    };
+    let missing: Vec<_> = REQUIRED_FIELDS
+        .iter()
+        .filter(|field| value.get(field).is_none_or(Value::is_null))
+        .collect();
+    if !missing.is_empty() {
+        for field in missing {
+            diagnostics.push(Diagnostic::new(path, Some(1), format!("missing required field `{field}`")));
+        }
+        return;
+    }
+    let info: EssayInfo = match front_matter.parse() {
+        Ok(info) => info,
+        Err(err) => return diagnostics.push(Diagnostic::front_matter(path, err)),
+    };
+
+    if NaiveDateTime::parse_from_str(&info.date, DATE_FORMAT).is_err() {
+        diagnostics.push(Diagnostic::new(
+            path,
+            front_matter.field_line("date"),
+            format!("cannot parse date `{}`, expected `YYYY-MM-DD HH:MM:SS`", info.date),
+        ));
+    }
+    if let Some(publish_at) = &info.publish_at {
+        if normalize_datetime(publish_at).is_none() {
+            diagnostics.push(Diagnostic::new(
+                path,
+                front_matter.field_line("publish_at"),
+                format!("cannot parse publish_at `{publish_at}`, expected `YYYY-MM-DD HH:MM:SS`"),
+            ));
+        }
+    }
+    if info.brief.trim().is_empty() {
+        diagnostics.push(Diagnostic::new(path, front_matter.field_line("brief"), String::from("brief is empty")));
+    }
+    if info.eid.is_empty() {
+        return;
+    }
+    match eids.get(&info.eid) {
+        Some(first) => diagnostics.push(Diagnostic::new(
+            path,
+            front_matter.field_line("eid"),
+            format!("duplicate eid `{}`, already used by {first}", info.eid),
+        )),
+        None => {
+            eids.insert(info.eid, path.to_string());
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 按顺序检查内存中的文章，eid 的重复检查在这些文章之间进行
+    async fn check(essays: &[(&str, &str)]) -> Vec<Diagnostic> {
+        let renderer = MarkdownRenderer::new();
+        let mut eids = HashMap::new();
+        let mut diagnostics = Vec::new();
+        for (path, content) in essays {
+            check_essay(path, content, &renderer, &mut eids, &mut diagnostics).await;
+        }
+        diagnostics
+    }
+
+    fn essay(eid: &str, date: &str, brief: &str, body: &str) -> String {
+        format!(
+            "---\neid: {eid}\ntitle: Title\ndate: {date}\ncategories: [a]\ntags: [b]\nbrief: \"{brief}\"\n---\n{body}"
+        )
+    }
+
+    fn lines(diagnostics: &[Diagnostic]) -> Vec<(Option<usize>, Option<usize>, &str)> {
+        diagnostics.iter().map(|d| (d.line, d.column, d.message.as_str())).collect()
+    }
+
+    #[tokio::test]
+    async fn valid_essay_has_no_diagnostics() {
+        let content = essay("e1", "2024-01-05 09:00:00", "brief", "# Hello\n\ntext\n");
+        assert!(check(&[("a.md", &content)]).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn missing_fields_are_reported_together() {
+        let diagnostics = check(&[("a.md", "---\ntitle: Title\ndate: 2024-01-05 09:00:00\n---\nbody\n")]).await;
+        assert_eq!(
+            lines(&diagnostics),
+            [
+                (Some(1), None, "missing required field `categories`"),
+                (Some(1), None, "missing required field `tags`"),
+                (Some(1), None, "missing required field `brief`"),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn field_errors_point_at_their_lines() {
+        let content = essay("e1", "2024/01/05", " ", "text\n");
+        let diagnostics = check(&[("a.md", &content)]).await;
+        assert_eq!(
+            lines(&diagnostics),
+            [
+                (Some(4), None, "cannot parse date `2024/01/05`, expected `YYYY-MM-DD HH:MM:SS`"),
+                (Some(7), None, "brief is empty"),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn duplicate_eid_names_the_first_file() {
+        let content = essay("e1", "2024-01-05 09:00:00", "brief", "text\n");
+        let diagnostics = check(&[("a.md", &content), ("b.md", &content)]).await;
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].to_string(), "b.md:2: duplicate eid `e1`, already used by a.md");
+    }
+
+    #[tokio::test]
+    async fn syntax_errors_carry_line_and_column() {
+        let diagnostics = check(&[("a.md", "---\ntitle: Title\ntags: [a\n---\nbody\n")]).await;
+        assert_eq!(diagnostics.len(), 1);
+        // 未闭合的列表在下一行 (front matter 结束处) 才能发现
+        assert_eq!((diagnostics[0].line, diagnostics[0].column), (Some(4), Some(1)));
+        assert!(diagnostics[0].to_string().starts_with("a.md:4:1: invalid front matter: "), "{}", diagnostics[0]);
+    }
+
+    #[tokio::test]
+    async fn render_warnings_are_offset_by_the_front_matter() {
+        let content = essay("e1", "2024-01-05 09:00:00", "brief", "text\n\n![](a.png)\n");
+        let diagnostics = check(&[("a.md", &content)]).await;
+        assert_eq!(lines(&diagnostics), [(Some(11), None, "image `a.png` has no alt text")]);
+    }
+
+    #[tokio::test]
+    async fn unreadable_files_do_not_stop_the_check() {
+        let diagnostics = check_essays(&[String::from("/nonexistent/a.md")], &MarkdownRenderer::new()).await;
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].line, None);
+        assert!(diagnostics[0].message.starts_with("cannot read file: "));
+    }
+}
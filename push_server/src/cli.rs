@@ -0,0 +1,122 @@
+use anyhow::{bail, Result};
+
+pub const USAGE: &str = "\
+usage: push_server [command] [options]
+
+commands:
+    sync     push new and changed essays to the database (default)
+    check    validate every essay under essays_source without touching the database
+    watch    sync once, then keep watching essays_source and sync changed essays as they are saved
+    migrate  apply pending database schema migrations (sync and watch also do this on startup)
+
+sync options:
+    --dry-run          print the planned INSERT/UPDATE/DELETE set without changing anything
+    --force            re-push every essay even if its content hash is unchanged
+    --report <path>    write a JSON sync report to <path>, `-` for stdout
+";
+
+/// 命令行参数
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    Sync(SyncOptions),
+    Check,
+    Watch,
+    Migrate,
+    Help,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SyncOptions {
+    pub dry_run: bool,
+    /// 忽略 hash，更新所有文章
+    pub force: bool,
+    /// json 报告的输出路径，`-` 为标准输出
+    pub report: Option<String>,
+}
+
+impl Command {
+    pub fn from_args() -> Result<Self> {
+        Self::parse(std::env::args().skip(1))
+    }
+
+    fn parse(args: impl Iterator<Item = String>) -> Result<Self> {
+        let mut args = args.peekable();
+        let command = match args.peek().map(String::as_str) {
+            None => return Ok(Self::Sync(SyncOptions::default())),
+            Some("check") => Self::Check,
+            Some("watch") => Self::Watch,
+            Some("migrate") => Self::Migrate,
+            Some("help" | "-h" | "--help") => Self::Help,
+            Some("sync") => Self::Sync(SyncOptions::default()),
+            // 省略命令时默认为 sync
+            Some(arg) if arg.starts_with("--") => {
+                return Ok(Self::Sync(SyncOptions::parse(args)?));
+            },
+            Some(other) => bail!("unknown command `{other}`\n\n{USAGE}"),
+        };
+        args.next();
+        match command {
+            Self::Sync(_) => Ok(Self::Sync(SyncOptions::parse(args)?)),
+            command => match args.next() {
+                Some(arg) => bail!("unexpected argument `{arg}`\n\n{USAGE}"),
+                None => Ok(command),
+            },
+        }
+    }
+}
+
+impl SyncOptions {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Self> {
+        let mut res = Self::default();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--dry-run" => res.dry_run = true,
+                "--force" => res.force = true,
+                "--report" => match args.next() {
+                    Some(path) => res.report = Some(path),
+                    None => bail!("`--report` needs a path\n\n{USAGE}"),
+                },
+                _ => bail!("unexpected argument `{arg}`\n\n{USAGE}"),
+            }
+        }
+        Ok(res)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Command> {
+        Command::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn sync_is_the_default_command() {
+        assert_eq!(parse(&[]).unwrap(), Command::Sync(SyncOptions::default()));
+        assert_eq!(parse(&["sync"]).unwrap(), Command::Sync(SyncOptions::default()));
+        let options = SyncOptions { dry_run: true, force: false, report: Some(String::from("-")) };
+        assert_eq!(parse(&["--dry-run", "--report", "-"]).unwrap(), Command::Sync(options.clone()));
+        assert_eq!(parse(&["sync", "--report", "-", "--dry-run"]).unwrap(), Command::Sync(options));
+    }
+
+    #[test]
+    fn other_commands_take_no_arguments() {
+        assert_eq!(parse(&["check"]).unwrap(), Command::Check);
+        assert_eq!(parse(&["watch"]).unwrap(), Command::Watch);
+        assert_eq!(parse(&["migrate"]).unwrap(), Command::Migrate);
+        assert_eq!(parse(&["-h"]).unwrap(), Command::Help);
+        let err = parse(&["check", "--force"]).unwrap_err().to_string();
+        assert!(err.starts_with("unexpected argument `--force`"), "{err}");
+    }
+
+    #[test]
+    fn bad_arguments_are_rejected() {
+        let err = parse(&["push"]).unwrap_err().to_string();
+        assert!(err.starts_with("unknown command `push`"), "{err}");
+        let err = parse(&["sync", "--forse"]).unwrap_err().to_string();
+        assert!(err.starts_with("unexpected argument `--forse`"), "{err}");
+        let err = parse(&["--report"]).unwrap_err().to_string();
+        assert!(err.starts_with("`--report` needs a path"), "{err}");
+    }
+}
@@ -1,16 +1,30 @@
-use pulldown_cmark::{html, Options, Parser};
 use serde::{Deserialize, Serialize};
-use tokio::{fs, io::{AsyncBufReadExt, BufReader}};
-use anyhow::Result;
+use tokio::fs;
+use anyhow::{Context, Result};
+use sha2::{Digest, Sha256};
+
+use crate::{front_matter::FrontMatter, time::{normalize_datetime, DATE_FORMAT}};
+
+pub use crate::markdown::{MarkdownRenderer, RenderWarning, Rendered, RendererConfig, TocEntry};
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct EssayInfo {
+    /// 没有写 eid 的新文章为空，同步时由 push_server 生成并写回文件
+    #[serde(default)]
     pub eid: String,
     pub title: String,
     pub date: String,
     pub categories: Vec<String>,
     pub tags: Vec<String>,
     pub brief: String,
+    /// 草稿不会出现在公开的接口中，只能通过预览地址查看。
+    /// 和 `publish_at` 一样只在服务端使用，不会序列化到接口返回的 json 中
+    #[serde(default, skip_serializing)]
+    pub draft: bool,
+    /// 定时发布的时间，格式和 `date` 相同，是博客服务所在服务器的本地时间。
+    /// 到达之前不会出现在公开的接口中。读取文件时统一为 [`DATE_FORMAT`] 格式
+    #[serde(default, skip_serializing)]
+    pub publish_at: Option<String>,
 }
 impl EssayInfo {
     pub fn new(
@@ -22,12 +36,142 @@ impl EssayInfo {
         brief: String,
     ) -> Self {
         Self {
-            eid, title, date, categories, tags, brief
+            eid, title, date, categories, tags, brief,
+            draft: false,
+            publish_at: None,
+        }
+    }
+}
+
+/// 文章列表的排序字段
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EssaySortKey {
+    #[default]
+    Date,
+    Title,
+    LastSaveTime,
+}
+
+impl EssaySortKey {
+    /// 对应 essays 表中的列名
+    pub fn column(&self) -> &'static str {
+        match self {
+            Self::Date => "date",
+            Self::Title => "title",
+            Self::LastSaveTime => "last_save_time",
         }
     }
 }
+
+/// 排序方向
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SortOrder {
+    Asc,
+    #[default]
+    Desc,
+}
+
+impl SortOrder {
+    pub fn keyword(&self) -> &'static str {
+        match self {
+            Self::Asc => "ASC",
+            Self::Desc => "DESC",
+        }
+    }
+}
+
+/// 多个 tag / category 过滤条件之间的关系
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MatchMode {
+    /// 文章必须满足所有条件
+    #[default]
+    All,
+    /// 文章满足任一条件即可
+    Any,
+}
+
+/// 文章列表的分页查询条件，`page` 从 1 开始
+#[derive(Debug, Clone)]
+pub struct EssayQuery {
+    pub page: u32,
+    pub per_page: u32,
+    pub sort: EssaySortKey,
+    pub order: SortOrder,
+    pub tags: Vec<String>,
+    pub categories: Vec<String>,
+    pub mode: MatchMode,
+}
+
+impl EssayQuery {
+    pub fn offset(&self) -> u64 {
+        (self.page.max(1) as u64 - 1) * self.per_page as u64
+    }
+}
+
+/// tag 或 category 以及拥有它的文章数
+#[derive(Debug, Clone, Serialize)]
+pub struct TermCount {
+    pub name: String,
+    pub count: u64,
+}
+
+/// 一条搜索结果
+#[derive(Debug, Clone, Serialize)]
+pub struct SearchHit {
+    #[serde(flatten)]
+    pub info: EssayInfo,
+    pub score: f64,
+    /// 命中附近的文本，命中的词用 `<mark>` 标出
+    pub snippet: String,
+}
+
+/// 文章的一个历史版本的概要
+#[derive(Debug, Clone, Serialize)]
+pub struct RevisionInfo {
+    pub eid: String,
+    /// 每篇文章单独编号，从 1 开始
+    pub revision: u32,
+    pub title: String,
+    /// 这个版本写入数据库的时间 (unix 时间戳)
+    pub saved_at: f64,
+    /// 这个版本被新版本替换的时间 (unix 时间戳)
+    pub archived_at: f64,
+    /// 这个版本是否是草稿，只用于 [`Self::was_published`]，不会序列化
+    #[serde(skip_serializing)]
+    pub draft: bool,
+    /// 这个版本的定时发布时间，格式和 `date` 相同，同样不会序列化
+    #[serde(skip_serializing)]
+    pub publish_at: Option<String>,
+}
+
+impl RevisionInfo {
+    /// 这个版本被替换之前是否公开过：不是草稿，并且没有定时发布或者发布时间早于被替换的时间。
+    /// 没有公开过的版本和不存在的版本一样不出现在公开的接口中
+    pub fn was_published(&self) -> bool {
+        let archived = chrono::DateTime::from_timestamp(self.archived_at as i64, 0)
+            .map(|time| time.with_timezone(&chrono::Local).format(DATE_FORMAT).to_string())
+            .unwrap_or_default();
+        !self.draft && self.publish_at.as_deref().is_none_or(|publish_at| publish_at <= archived.as_str())
+    }
+}
+
+/// 文章被更新前的一个版本，content 是渲染后的 html
+#[derive(Debug, Clone, Serialize)]
+pub struct Revision {
+    #[serde(flatten)]
+    pub info: RevisionInfo,
+    pub date: String,
+    pub brief: String,
+    pub categories: Vec<String>,
+    pub tags: Vec<String>,
+    pub content: String,
+}
+
 /// Essay class
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Essay {
     pub eid: String,
     pub title: String,
@@ -36,6 +180,15 @@ pub struct Essay {
     pub tags: Vec<String>,
     pub brief: String,
     pub content: String,
+    /// 由标题生成的目录
+    #[serde(default)]
+    pub toc: Vec<TocEntry>,
+    /// 见 [`EssayInfo::draft`]
+    #[serde(default, skip_serializing)]
+    pub draft: bool,
+    /// 见 [`EssayInfo::publish_at`]
+    #[serde(default, skip_serializing)]
+    pub publish_at: Option<String>,
 }
 
 impl Essay {
@@ -50,39 +203,86 @@ impl Essay {
     ) -> Self {
         Self {
             eid, title, date, categories, tags, brief, content,
+            toc: Vec::new(),
+            draft: false,
+            publish_at: None,
         }
     }
-    /// 从 markdown 文件路径得到一个 Essay class
+
+    /// 在 `now` 时是否已经发布，`now` 的格式和 `date` 相同，见 [`crate::now_date`]
+    pub fn is_published(&self, now: &str) -> bool {
+        !self.draft && self.publish_at.as_deref().is_none_or(|publish_at| publish_at <= now)
+    }
+
+    /// 历史版本中保存的字段 (标题、日期、简介、categories、tags 和内容) 的 sha256，十六进制。
+    /// 同步时只有这些字段变化的文章才会存入历史版本
+    pub fn version_hash(&self) -> String {
+        let fields = (&self.title, &self.date, &self.brief, &self.categories, &self.tags, &self.content);
+        let json = serde_json::to_string(&fields).unwrap_or_default();
+        format!("{:x}", Sha256::digest(json.as_bytes()))
+    }
+    /// 从 markdown 文件路径得到一个 Essay class，渲染时的 warning 直接打印出来
     pub async fn crate_from_path(
         path: &str,
+        renderer: &MarkdownRenderer,
     ) -> Result<Self> {
-        let file = fs::File::open(path).await?;
-        let mut yaml = Vec::new();
-        let mut md_content = Vec::new();
-        let mut in_yaml_block = false;
-        let reader = BufReader::new(file);
-        let mut lines = reader.lines();
-        while let Some(line) = lines.next_line().await.transpose() {
-            let line = line?;
-            if line.trim().eq("---") {
-                in_yaml_block = !in_yaml_block;
-            } else {
-                if in_yaml_block {
-                    yaml.push(line)
-                } else {
-                    md_content.push(line)
-                }
-            }
+        let loaded = Self::load_from_path(path, renderer).await?;
+        for warning in &loaded.warnings {
+            println!("->> {:<12} - {path}:{}: {}", "WARN", warning.line, warning.message);
+        }
+        Ok(loaded.essay)
+    }
+
+    /// 从 markdown 文件路径得到一个 Essay class 以及渲染时的 warning 和源文件的 hash
+    pub async fn load_from_path(
+        path: &str,
+        renderer: &MarkdownRenderer,
+    ) -> Result<LoadedEssay> {
+        let content = fs::read_to_string(path).await?;
+        let front_matter = FrontMatter::split(&content).with_context(|| format!("{path}: invalid front matter"))?;
+        let mut essay_info: EssayInfo = front_matter.parse().with_context(|| format!("{path}: invalid front matter"))?;
+        // 数据库中按字符串和当前时间比较，格式不对的时间无法正确比较，拒绝同步这篇文章
+        if let Some(publish_at) = &essay_info.publish_at {
+            let normalized = normalize_datetime(publish_at).with_context(|| {
+                format!("{path}: cannot parse publish_at `{publish_at}`, expected `YYYY-MM-DD HH:MM:SS`")
+            })?;
+            essay_info.publish_at = Some(normalized);
         }
-        let content = MarkdownRenderer::new().render(&md_content.join("\n")).await;
-        let yaml = yaml.join("\n");
-        let essay_info: EssayInfo = serde_yaml::from_str(&yaml).expect(&(String::from(path) + " yaml don't accepted <<-\n"));
+        let rendered = renderer.render(front_matter.body).await?;
+        let warnings = rendered
+            .warnings
+            .into_iter()
+            .map(|warning| RenderWarning {
+                line: warning.line + front_matter.body_offset,
+                message: warning.message,
+            })
+            .collect();
         let mut res = Self::from(essay_info);
-        res.content = content;
-        Ok(res)
+        res.content = rendered.html;
+        res.toc = rendered.toc;
+        Ok(LoadedEssay {
+            essay: res,
+            warnings,
+            content_hash: content_hash(&content),
+        })
     }
 }
 
+/// 从文件读取的文章
+#[derive(Debug, Clone)]
+pub struct LoadedEssay {
+    pub essay: Essay,
+    /// 渲染时的 warning，行号是在文件中的行号
+    pub warnings: Vec<RenderWarning>,
+    /// 整个源文件 (front matter 和正文) 的 sha256，十六进制
+    pub content_hash: String,
+}
+
+/// 源文件内容的 sha256，用于判断文章是否需要更新
+pub fn content_hash(content: &str) -> String {
+    format!("{:x}", Sha256::digest(content.as_bytes()))
+}
+
 impl From<EssayInfo> for Essay {
     fn from(essay_info: EssayInfo) -> Self {
         Self {
@@ -93,27 +293,37 @@ impl From<EssayInfo> for Essay {
             tags: essay_info.tags,
             brief: essay_info.brief,
             content: Default::default(),
+            toc: Vec::new(),
+            draft: essay_info.draft,
+            publish_at: essay_info.publish_at,
         }
     }
 }
 
-/// markdown 渲染器
-pub struct MarkdownRenderer {
-    option: Options,
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-impl MarkdownRenderer {
-    pub fn new() -> Self {
-        let mut res = Self {
-            option: Options::empty()
-        };
-        res.option.insert(Options::ENABLE_STRIKETHROUGH);
-        res
-    }
-    pub async fn render(&self, md_content: &str) -> String {
-        let parser = Parser::new_ext(md_content, self.option);
-        let mut html_output = String::new();
-        html::push_html(&mut html_output, parser);
-        html_output
+    #[test]
+    fn publish_state_is_read_but_not_serialized() {
+        let info: EssayInfo = serde_json::from_value(serde_json::json!({
+            "eid": "e1",
+            "title": "t",
+            "date": "2024-01-05 09:00:00",
+            "categories": [],
+            "tags": [],
+            "brief": "b",
+            "draft": true,
+            "publish_at": "2030-01-01 00:00:00",
+        }))
+        .unwrap();
+        assert!(info.draft);
+        assert_eq!(info.publish_at.as_deref(), Some("2030-01-01 00:00:00"));
+
+        let json = serde_json::to_value(&info).unwrap();
+        assert_eq!(json["eid"], "e1");
+        assert!(json.get("draft").is_none() && json.get("publish_at").is_none(), "{json}");
+        let json = serde_json::to_value(Essay::from(info)).unwrap();
+        assert!(json.get("draft").is_none() && json.get("publish_at").is_none(), "{json}");
     }
 }
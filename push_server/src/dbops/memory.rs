@@ -0,0 +1,317 @@
+use std::{
+    cmp::Ordering,
+    collections::{HashMap, HashSet},
+    path::Path,
+    sync::{Arc, RwLock},
+    time::{Duration, UNIX_EPOCH},
+};
+
+use anyhow::{bail, Result};
+use async_trait::async_trait;
+use notify_debouncer_mini::{new_debouncer, notify::RecursiveMode, DebounceEventResult};
+use tokio::sync::mpsc;
+
+use crate::{
+    data_struct::{
+        Essay, EssayInfo, EssayQuery, EssaySortKey, MarkdownRenderer, MatchMode, Revision, RevisionInfo, SearchHit,
+        SortOrder, TermCount,
+    },
+    dbops::store::{EssayStore, SyncSession},
+    get_entries, now_date, search,
+};
+
+/// 文件变化后等待这么久再重新加载，合并编辑器保存时的多个事件
+const RELOAD_DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// 不使用数据库，直接从 markdown 目录加载所有文章的只读存储。
+/// 适合文章不多的小型部署，调用 [`MemoryStore::watch`] 后文件变化时自动重新加载
+pub struct MemoryStore {
+    source: String,
+    renderer: MarkdownRenderer,
+    index: RwLock<Arc<MemoryIndex>>,
+}
+
+/// 某一时刻加载的所有文章
+#[derive(Default)]
+struct MemoryIndex {
+    /// 按文件路径排序
+    essays: Vec<(Essay, f64)>,
+    /// 全文索引 (eid, 词, 权重)
+    terms: Vec<(String, String, f64)>,
+}
+
+impl MemoryStore {
+    /// 加载 `source` 目录下所有的 markdown 文章
+    pub async fn load(source: &str, renderer: MarkdownRenderer) -> Result<Self> {
+        let res = Self {
+            source: source.to_string(),
+            renderer,
+            index: RwLock::new(Arc::default()),
+        };
+        res.reload().await?;
+        Ok(res)
+    }
+
+    /// 重新加载整个目录，加载完成后一次替换，读取中的请求不会看到一半的结果。
+    /// 读取失败、没有 eid 或 eid 重复的文章被跳过并打印出来
+    pub async fn reload(&self) -> Result<()> {
+        let mut paths = get_entries(&self.source, "md")?;
+        paths.sort();
+        let mut index = MemoryIndex::default();
+        let mut eids = HashMap::new();
+        for path in paths {
+            let essay = match Essay::crate_from_path(&path, &self.renderer).await {
+                Ok(essay) => essay,
+                Err(err) => {
+                    println!("->> {:<12} - {err:#}", "ERROR");
+                    continue;
+                },
+            };
+            if essay.eid.is_empty() {
+                println!("->> {:<12} - {path}: no eid, run `push_server sync` to assign one", "SKIP");
+                continue;
+            }
+            if let Some(first) = eids.insert(essay.eid.clone(), path.clone()) {
+                println!("->> {:<12} - {path}: duplicate eid `{}`, already used by {first}", "SKIP", essay.eid);
+                continue;
+            }
+            index.terms.extend(
+                search::essay_terms(&essay)
+                    .into_iter()
+                    .map(|(term, weight)| (essay.eid.clone(), term, weight)),
+            );
+            index.essays.push((essay, modified_time(&path)));
+        }
+        println!("->> {:<12} - {} essays from {}", "LOAD", index.essays.len(), self.source);
+        *self.index.write().unwrap() = Arc::new(index);
+        Ok(())
+    }
+
+    /// 监听文章目录，有变化时重新加载。一直运行，应该放在单独的任务中
+    pub async fn watch(self: Arc<Self>) -> Result<()> {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let mut debouncer = new_debouncer(RELOAD_DEBOUNCE, move |res: DebounceEventResult| {
+            let _ = tx.send(res);
+        })?;
+        debouncer
+            .watcher()
+            .watch(Path::new(&self.source), RecursiveMode::Recursive)?;
+        while let Some(res) = rx.recv().await {
+            let changed = match res {
+                Ok(events) => events.iter().any(|event| {
+                    event.path.is_dir() || event.path.extension().is_some_and(|ext| ext == "md")
+                }),
+                Err(err) => {
+                    println!("->> {:<12} - {err}", "ERROR");
+                    continue;
+                },
+            };
+            if changed {
+                if let Err(err) = self.reload().await {
+                    println!("->> {:<12} - {err:#}", "ERROR");
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn index(&self) -> Arc<MemoryIndex> {
+        self.index.read().unwrap().clone()
+    }
+}
+
+impl MemoryIndex {
+    /// 在 `now` 时已经发布的文章，和数据库一样在查询时判断，定时发布的文章到时间后自动出现
+    fn published(&self, now: &str) -> Vec<&(Essay, f64)> {
+        self.essays.iter().filter(|(essay, _)| essay.is_published(now)).collect()
+    }
+
+    /// 按 `query` 过滤、排序，返回所有已经发布并且满足条件的文章
+    fn filter(&self, query: &EssayQuery) -> Vec<&(Essay, f64)> {
+        let mut res: Vec<_> = self
+            .published(&now_date())
+            .into_iter()
+            .filter(|(essay, _)| matches_query(essay, query))
+            .collect();
+        res.sort_by(|(a, a_time), (b, b_time)| {
+            let ordering = match query.sort {
+                EssaySortKey::Date => a.date.cmp(&b.date),
+                EssaySortKey::Title => a.title.cmp(&b.title),
+                EssaySortKey::LastSaveTime => a_time.partial_cmp(b_time).unwrap_or(Ordering::Equal),
+            };
+            let ordering = match query.order {
+                SortOrder::Asc => ordering,
+                SortOrder::Desc => ordering.reverse(),
+            };
+            ordering.then_with(|| a.eid.cmp(&b.eid))
+        });
+        res
+    }
+
+    /// `filter` 之后按 `query` 分页
+    fn page(&self, query: &EssayQuery) -> (Vec<&(Essay, f64)>, u64) {
+        let res = self.filter(query);
+        let total = res.len() as u64;
+        let res = res
+            .into_iter()
+            .skip(query.offset() as usize)
+            .take(query.per_page as usize)
+            .collect();
+        (res, total)
+    }
+
+    /// 已经发布的文章中每个名字下的文章数，名字不区分大小写，和数据库中的排序规则一致
+    fn counts(&self, names: impl Fn(&Essay) -> &[String]) -> Vec<TermCount> {
+        let mut counts: Vec<TermCount> = Vec::new();
+        let mut positions = HashMap::new();
+        for (essay, _) in self.published(&now_date()) {
+            let mut seen = HashSet::new();
+            for name in names(essay) {
+                let key = name.to_lowercase();
+                if !seen.insert(key.clone()) {
+                    continue;
+                }
+                let i = *positions.entry(key).or_insert_with(|| {
+                    counts.push(TermCount { name: name.clone(), count: 0 });
+                    counts.len() - 1
+                });
+                counts[i].count += 1;
+            }
+        }
+        counts.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.name.cmp(&b.name)));
+        counts
+    }
+}
+
+/// 文章是否满足 `query` 的 tags 和 categories 条件
+fn matches_query(essay: &Essay, query: &EssayQuery) -> bool {
+    let contains = |names: &[String], name: &str| names.iter().any(|n| n.to_lowercase() == name.to_lowercase());
+    let mut conditions = query
+        .tags
+        .iter()
+        .map(|tag| contains(&essay.tags, tag))
+        .chain(query.categories.iter().map(|category| contains(&essay.categories, category)))
+        .peekable();
+    if conditions.peek().is_none() {
+        return true;
+    }
+    match query.mode {
+        MatchMode::All => conditions.all(|matched| matched),
+        MatchMode::Any => conditions.any(|matched| matched),
+    }
+}
+
+fn essay_info(essay: &Essay) -> EssayInfo {
+    let mut info = EssayInfo::new(
+        essay.eid.clone(),
+        essay.title.clone(),
+        essay.date.clone(),
+        essay.categories.clone(),
+        essay.tags.clone(),
+        essay.brief.clone(),
+    );
+    info.draft = essay.draft;
+    info.publish_at = essay.publish_at.clone();
+    info
+}
+
+/// 文件的修改时间，作为文章的最后保存时间
+fn modified_time(path: &str) -> f64 {
+    std::fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map_or(0.0, |duration| duration.as_secs_f64())
+}
+
+#[async_trait]
+impl EssayStore for MemoryStore {
+    async fn migrate(&self) -> Result<Vec<(i64, String)>> {
+        Ok(Vec::new())
+    }
+
+    async fn query_essays_last_save_time(&self) -> Result<HashMap<String, f64>> {
+        Ok(self
+            .index()
+            .published(&now_date())
+            .into_iter()
+            .map(|(essay, last_save_time)| (essay.eid.clone(), *last_save_time))
+            .collect())
+    }
+
+    async fn query_essays_sync_state(&self) -> Result<HashMap<String, (String, Option<String>)>> {
+        Ok(self
+            .index()
+            .essays
+            .iter()
+            .map(|(essay, _)| (essay.eid.clone(), (essay.title.clone(), None)))
+            .collect())
+    }
+
+    async fn query_essay(&self, eid: &str) -> Result<Option<(Essay, f64)>> {
+        Ok(self.index().essays.iter().find(|(essay, _)| essay.eid == eid).cloned())
+    }
+
+    async fn query_essay_info_page(&self, query: &EssayQuery) -> Result<(Vec<EssayInfo>, u64)> {
+        let index = self.index();
+        let (essays, total) = index.page(query);
+        Ok((essays.into_iter().map(|(essay, _)| essay_info(essay)).collect(), total))
+    }
+
+    async fn query_essay_page(&self, query: &EssayQuery) -> Result<Vec<(Essay, f64)>> {
+        Ok(self.index().page(query).0.into_iter().cloned().collect())
+    }
+
+    async fn query_tag_counts(&self) -> Result<Vec<TermCount>> {
+        Ok(self.index().counts(|essay| &essay.tags))
+    }
+
+    async fn query_category_counts(&self) -> Result<Vec<TermCount>> {
+        Ok(self.index().counts(|essay| &essay.categories))
+    }
+
+    async fn search_essays(&self, q: &str, limit: u32) -> Result<(Vec<SearchHit>, u64)> {
+        let terms = search::query_terms(q);
+        if terms.is_empty() {
+            return Ok((Vec::new(), 0));
+        }
+        let index = self.index();
+        let published: HashMap<_, _> = index
+            .published(&now_date())
+            .into_iter()
+            .map(|(essay, _)| (essay.eid.as_str(), essay))
+            .collect();
+        let hits: Vec<_> = index
+            .terms
+            .iter()
+            .filter(|(eid, term, _)| terms.contains(term) && published.contains_key(eid.as_str()))
+            .cloned()
+            .collect();
+        let ranked = search::rank(&hits, published.len() as u64);
+        let count = ranked.len() as u64;
+
+        let mut res = Vec::new();
+        for (eid, score) in ranked.into_iter().take(limit as usize) {
+            if let Some(essay) = published.get(eid.as_str()) {
+                let text = search::strip_html(&essay.content);
+                let text = if text.is_empty() { essay.brief.clone() } else { text };
+                let snippet = search::snippet(&text, &terms);
+                res.push(SearchHit { info: essay_info(essay), score, snippet });
+            }
+        }
+        Ok((res, count))
+    }
+
+    // 文章直接来自文件，不保存历史版本
+    async fn query_revisions(&self, _eid: &str) -> Result<Vec<RevisionInfo>> {
+        Ok(Vec::new())
+    }
+
+    async fn query_revision(&self, _eid: &str, _revision: u32) -> Result<Option<Revision>> {
+        Ok(None)
+    }
+
+    async fn begin_sync(&self) -> Result<Box<dyn SyncSession>> {
+        bail!("the in-memory store is read-only, it reloads from {} when files change", self.source)
+    }
+}
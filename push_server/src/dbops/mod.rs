@@ -1,2 +1,5 @@
+pub mod memory;
+pub mod sqlite_ops;
+pub mod store;
 pub mod tables_ops;
-pub mod utils;
\ No newline at end of file
+pub mod utils;
@@ -0,0 +1,60 @@
+use async_trait::async_trait;
+use sqlx::{self, sqlite::SqliteRow, Row, Sqlite, SqliteConnection};
+use crate::{
+    data_struct::Essay,
+    dbops::tables_ops::{value_rows, Dialect, Taxonomy},
+    sync::SyncWrite,
+};
+use anyhow::Result;
+
+// 查询和写入都在 tables_ops 的 `Dialect` 中和 MySQL 共用，这里只有 SQLite 不同的部分。
+// SQLite 中 date 和 publish_at 以 `%Y-%m-%d %H:%M:%S` 格式的文本保存
+
+impl Dialect for Sqlite {
+    const INSERT_IGNORE: &'static str = "INSERT OR IGNORE";
+
+    fn get_datetime(row: &SqliteRow, column: &str) -> Option<String> {
+        row.get(column)
+    }
+
+    fn link_rows(n: usize) -> String {
+        // SQLite 中 VALUES 子查询的列名为 column1, column2。
+        // 不用 UNION ALL，一条语句中 UNION 的数量默认最多 500
+        format!("SELECT column1 AS eid, column2 AS name FROM (VALUES {})", value_rows(n, 2))
+    }
+
+    fn taxonomy_json(taxonomy: &Taxonomy) -> String {
+        // json_group_array 在旧版本的 SQLite 中不支持 ORDER BY，先在子查询中排序
+        format!(
+            "(SELECT json_group_array(name) FROM (SELECT s.{} AS name FROM {} l JOIN {} s ON l.{} = s.id WHERE l.eid = e.eid ORDER BY s.id))",
+            taxonomy.name_column, taxonomy.link_table, taxonomy.set_table, taxonomy.id_column,
+        )
+    }
+}
+
+#[async_trait]
+impl SyncWrite for SqliteConnection {
+    async fn delete_essays(&mut self, eids: &[&str]) -> Result<()> {
+        Sqlite::delete_essays(self, eids).await
+    }
+
+    async fn archive_essays(&mut self, essays: &[&Essay], current_time: f64) -> Result<()> {
+        Sqlite::archive_essays(self, essays, current_time).await
+    }
+
+    async fn delete_essay_links(&mut self, eids: &[&str]) -> Result<()> {
+        Sqlite::delete_essay_links(self, eids).await
+    }
+
+    async fn insert_essay_info(&mut self, essay: &Essay, content_hash: &str, current_time: f64) -> Result<()> {
+        Sqlite::insert_essay_info(self, essay, content_hash, current_time).await
+    }
+
+    async fn update_essay_info(&mut self, essay: &Essay, content_hash: &str, current_time: f64) -> Result<()> {
+        Sqlite::update_essay_info(self, essay, content_hash, current_time).await
+    }
+
+    async fn insert_essay_links(&mut self, essays: &[&Essay]) -> Result<()> {
+        Sqlite::insert_essay_links(self, essays).await
+    }
+}
@@ -0,0 +1,241 @@
+use std::{collections::HashMap, sync::Arc};
+
+use anyhow::{bail, Result};
+use async_trait::async_trait;
+use sqlx::{pool::PoolConnection, MySql, Pool, Sqlite};
+
+use crate::{
+    data_struct::{Essay, EssayInfo, EssayQuery, Revision, RevisionInfo, SearchHit, TermCount},
+    dbops::{tables_ops::{Dialect, CATEGORIES, TAGS}, utils::*},
+    sync::{apply, SyncError, SyncPlan},
+    DATABASE_URL,
+};
+
+/// 文章的存储。博客服务和 push_server 只通过它读写数据库，
+/// 由 `DATABASE_URL` 的 scheme 决定使用 MySQL 还是 SQLite，见 [`connect`]。
+///
+/// 列表、计数和搜索只包括查询时已经发布的文章 (见 [`Essay::is_published`])，
+/// 同步状态和 [`EssayStore::query_essay`] 包括所有文章
+#[async_trait]
+pub trait EssayStore: Send + Sync {
+    /// 执行还没有执行过的迁移，返回这次执行的迁移 (版本, 描述)
+    async fn migrate(&self) -> Result<Vec<(i64, String)>>;
+
+    /// 所有已经发布的文章的最后保存时间
+    async fn query_essays_last_save_time(&self) -> Result<HashMap<String, f64>>;
+
+    /// 所有文章的标题和源文件 hash，同步时用来生成变更计划
+    async fn query_essays_sync_state(&self) -> Result<HashMap<String, (String, Option<String>)>>;
+
+    /// 完整的文章以及它的最后保存时间，文章不存在时返回 `None`。包括还没有发布的文章
+    async fn query_essay(&self, eid: &str) -> Result<Option<(Essay, f64)>>;
+
+    /// 按 `query` 过滤、分页、排序得到文章的 info，同时返回满足过滤条件的文章总数
+    async fn query_essay_info_page(&self, query: &EssayQuery) -> Result<(Vec<EssayInfo>, u64)>;
+
+    /// 和 `query_essay_info_page` 相同，但返回带内容的完整文章以及最后保存时间
+    async fn query_essay_page(&self, query: &EssayQuery) -> Result<Vec<(Essay, f64)>>;
+
+    /// 所有 tag 以及每个 tag 下的文章数
+    async fn query_tag_counts(&self) -> Result<Vec<TermCount>>;
+
+    /// 所有 category 以及每个 category 下的文章数
+    async fn query_category_counts(&self) -> Result<Vec<TermCount>>;
+
+    /// 全文搜索，返回按相关度排序的前 `limit` 条结果以及命中的文章总数
+    async fn search_essays(&self, q: &str, limit: u32) -> Result<(Vec<SearchHit>, u64)>;
+
+    /// 文章的所有历史版本，最新的在前。同步时每次更新文章都会保存被替换的版本
+    async fn query_revisions(&self, eid: &str) -> Result<Vec<RevisionInfo>>;
+
+    /// 文章的某个历史版本，不存在时返回 `None`
+    async fn query_revision(&self, eid: &str, revision: u32) -> Result<Option<Revision>>;
+
+    /// 开始一次同步，拿到同步锁。另一个同步正在进行时返回错误
+    async fn begin_sync(&self) -> Result<Box<dyn SyncSession>>;
+}
+
+/// 一次同步，持有同步锁直到 [`SyncSession::release`]
+#[async_trait]
+pub trait SyncSession: Send {
+    /// 在一个事务中按计划写入数据库
+    async fn apply(&mut self, plan: &SyncPlan, current_time: f64) -> Result<(), SyncError>;
+
+    /// 释放同步锁
+    async fn release(self: Box<Self>) -> Result<()>;
+}
+
+/// 按 `DATABASE_URL` 连接数据库，见 [`connect_url`]
+pub async fn connect() -> Result<Arc<dyn EssayStore>> {
+    connect_url(&DATABASE_URL).await
+}
+
+/// `mysql://` 或 `mariadb://` 使用 MySQL，`sqlite:` 使用 SQLite，
+/// 例如 `sqlite://blog.db` 或测试用的 `sqlite::memory:`
+pub async fn connect_url(url: &str) -> Result<Arc<dyn EssayStore>> {
+    match url.split_once(':').map(|(scheme, _)| scheme) {
+        Some("mysql" | "mariadb") => Ok(Arc::new(MySqlStore::new(build_mysql_pool(url).await?))),
+        Some("sqlite") => Ok(Arc::new(SqliteStore::new(build_sqlite_pool(url).await?))),
+        _ => bail!("unsupported DATABASE_URL `{url}`, expected `mysql://...` or `sqlite:...`"),
+    }
+}
+
+pub struct MySqlStore {
+    pool: Pool<MySql>,
+}
+
+impl MySqlStore {
+    pub fn new(pool: Pool<MySql>) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl EssayStore for MySqlStore {
+    async fn migrate(&self) -> Result<Vec<(i64, String)>> {
+        migrate(&MYSQL_MIGRATOR, &self.pool).await
+    }
+
+    async fn query_essays_last_save_time(&self) -> Result<HashMap<String, f64>> {
+        MySql::query_essays_last_save_time(&self.pool).await
+    }
+
+    async fn query_essays_sync_state(&self) -> Result<HashMap<String, (String, Option<String>)>> {
+        MySql::query_essays_sync_state(&self.pool).await
+    }
+
+    async fn query_essay(&self, eid: &str) -> Result<Option<(Essay, f64)>> {
+        MySql::query_essay(&self.pool, eid).await
+    }
+
+    async fn query_essay_info_page(&self, query: &EssayQuery) -> Result<(Vec<EssayInfo>, u64)> {
+        MySql::query_essay_info_page(&self.pool, query).await
+    }
+
+    async fn query_essay_page(&self, query: &EssayQuery) -> Result<Vec<(Essay, f64)>> {
+        MySql::query_essay_page(&self.pool, query).await
+    }
+
+    async fn query_tag_counts(&self) -> Result<Vec<TermCount>> {
+        MySql::query_term_counts(&self.pool, &TAGS).await
+    }
+
+    async fn query_category_counts(&self) -> Result<Vec<TermCount>> {
+        MySql::query_term_counts(&self.pool, &CATEGORIES).await
+    }
+
+    async fn search_essays(&self, q: &str, limit: u32) -> Result<(Vec<SearchHit>, u64)> {
+        MySql::search_essays(&self.pool, q, limit).await
+    }
+
+    async fn query_revisions(&self, eid: &str) -> Result<Vec<RevisionInfo>> {
+        MySql::query_revisions(&self.pool, eid).await
+    }
+
+    async fn query_revision(&self, eid: &str, revision: u32) -> Result<Option<Revision>> {
+        MySql::query_revision(&self.pool, eid, revision).await
+    }
+
+    async fn begin_sync(&self) -> Result<Box<dyn SyncSession>> {
+        let mut conn = self.pool.acquire().await?;
+        acquire_lock(&mut conn, SYNC_LOCK).await?;
+        Ok(Box::new(MySqlSyncSession { conn }))
+    }
+}
+
+/// MySQL 的同步锁是连接上的命名锁，整个同步期间持有这个连接
+struct MySqlSyncSession {
+    conn: PoolConnection<MySql>,
+}
+
+#[async_trait]
+impl SyncSession for MySqlSyncSession {
+    async fn apply(&mut self, plan: &SyncPlan, current_time: f64) -> Result<(), SyncError> {
+        apply(&mut *self.conn, plan, current_time).await
+    }
+
+    async fn release(mut self: Box<Self>) -> Result<()> {
+        release_lock(&mut self.conn, SYNC_LOCK).await
+    }
+}
+
+pub struct SqliteStore {
+    pool: Pool<Sqlite>,
+}
+
+impl SqliteStore {
+    pub fn new(pool: Pool<Sqlite>) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl EssayStore for SqliteStore {
+    async fn migrate(&self) -> Result<Vec<(i64, String)>> {
+        migrate(&SQLITE_MIGRATOR, &self.pool).await
+    }
+
+    async fn query_essays_last_save_time(&self) -> Result<HashMap<String, f64>> {
+        Sqlite::query_essays_last_save_time(&self.pool).await
+    }
+
+    async fn query_essays_sync_state(&self) -> Result<HashMap<String, (String, Option<String>)>> {
+        Sqlite::query_essays_sync_state(&self.pool).await
+    }
+
+    async fn query_essay(&self, eid: &str) -> Result<Option<(Essay, f64)>> {
+        Sqlite::query_essay(&self.pool, eid).await
+    }
+
+    async fn query_essay_info_page(&self, query: &EssayQuery) -> Result<(Vec<EssayInfo>, u64)> {
+        Sqlite::query_essay_info_page(&self.pool, query).await
+    }
+
+    async fn query_essay_page(&self, query: &EssayQuery) -> Result<Vec<(Essay, f64)>> {
+        Sqlite::query_essay_page(&self.pool, query).await
+    }
+
+    async fn query_tag_counts(&self) -> Result<Vec<TermCount>> {
+        Sqlite::query_term_counts(&self.pool, &TAGS).await
+    }
+
+    async fn query_category_counts(&self) -> Result<Vec<TermCount>> {
+        Sqlite::query_term_counts(&self.pool, &CATEGORIES).await
+    }
+
+    async fn search_essays(&self, q: &str, limit: u32) -> Result<(Vec<SearchHit>, u64)> {
+        Sqlite::search_essays(&self.pool, q, limit).await
+    }
+
+    async fn query_revisions(&self, eid: &str) -> Result<Vec<RevisionInfo>> {
+        Sqlite::query_revisions(&self.pool, eid).await
+    }
+
+    async fn query_revision(&self, eid: &str, revision: u32) -> Result<Option<Revision>> {
+        Sqlite::query_revision(&self.pool, eid, revision).await
+    }
+
+    async fn begin_sync(&self) -> Result<Box<dyn SyncSession>> {
+        let holder = acquire_table_lock(&self.pool, SYNC_LOCK).await?;
+        Ok(Box::new(SqliteSyncSession { pool: self.pool.clone(), holder }))
+    }
+}
+
+/// SQLite 的同步锁是 `sync_lock` 表中的一行，见 [`acquire_table_lock`]。
+/// 连接只在写入时取得，内存数据库只有一个连接，不能在整个同步期间占用
+struct SqliteSyncSession {
+    pool: Pool<Sqlite>,
+    holder: String,
+}
+
+#[async_trait]
+impl SyncSession for SqliteSyncSession {
+    async fn apply(&mut self, plan: &SyncPlan, current_time: f64) -> Result<(), SyncError> {
+        let mut conn = self.pool.acquire().await.map_err(SyncError::database)?;
+        apply(&mut *conn, plan, current_time).await
+    }
+
+    async fn release(self: Box<Self>) -> Result<()> {
+        release_table_lock(&self.pool, SYNC_LOCK, &self.holder).await
+    }
+}
@@ -1,377 +1,797 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeSet, HashMap};
 use chrono::NaiveDateTime;
-use sqlx::{self, MySql, Pool, Row};
-use crate::data_struct::{Essay, EssayInfo};
+use async_trait::async_trait;
+use sqlx::{
+    self, database::HasArguments, mysql::MySqlRow, ColumnIndex, Database, Decode, Encode, Executor, IntoArguments, MySql,
+    MySqlConnection, Pool, Row, Type,
+};
+use crate::{
+    data_struct::{Essay, EssayInfo, EssayQuery, MatchMode, Revision, RevisionInfo, SearchHit, TermCount},
+    now_date, search,
+    sync::SyncWrite,
+    time::DATE_FORMAT,
+};
 use anyhow::Result;
 
-/// 得到数据库中所有文章的最后保存时间
-pub async fn query_essays_last_save_time(
-    pool: &Pool<MySql>,
-) -> Result<HashMap<String, f64>> {
-    let mut res = HashMap::new();
-    let rows = sqlx::query(
-        r#"
-SELECT eid, last_save_time FROM essays
-        "#
-    )
-    .fetch_all(pool)
-    .await?;
-    for row in rows {
-        let eid: String = row.get("eid");
-        let last_save_time: f64= row.get("last_save_time");
-        res.insert(eid, last_save_time);
-    }
-    Ok(res)
+/// 已经发布的文章：不是草稿，并且没有定时发布或者发布时间已经过去。`?` 绑定 [`now_date`]。
+/// 只用到 essays 表的列，和其它表 JOIN 时也不需要表名
+pub(crate) const PUBLISHED: &str = "draft = 0 AND (publish_at IS NULL OR publish_at <= ?)";
+
+/// 每条语句最多写入的行数，避免单条语句的占位符过多
+pub(crate) const CHUNK_SIZE: usize = 1000;
+
+/// tag 或 category 用到的表和列
+pub(crate) struct Taxonomy {
+    pub(crate) set_table: &'static str,
+    pub(crate) name_column: &'static str,
+    pub(crate) link_table: &'static str,
+    pub(crate) id_column: &'static str,
+}
+
+pub(crate) const TAGS: Taxonomy = Taxonomy {
+    set_table: "tag_set",
+    name_column: "tag_name",
+    link_table: "essay_tag",
+    id_column: "tag_id",
+};
+
+pub(crate) const CATEGORIES: Taxonomy = Taxonomy {
+    set_table: "category_set",
+    name_column: "category_name",
+    link_table: "essay_category",
+    id_column: "category_id",
+};
+
+/// 一批文章的 tags 和 categories，按 eid 分组
+pub(crate) struct Taxonomies {
+    tags: HashMap<String, Vec<String>>,
+    categories: HashMap<String, Vec<String>>,
 }
 
-/// 根据文章的 eid 得到该文章的内容
-pub async fn query_essay_content(
-    pool: &Pool<MySql>,
-    eid: &str,
-) -> Result<Option<String>> {
-    Ok(
-        sqlx::query_scalar!(
-        r#"
-SELECT content
+/// 文章存储的所有查询和写入。SQL 只使用 MySQL 和 SQLite 都支持的写法 (占位符都是 `?`)，由默认实现共用，
+/// 两边不同的部分 (忽略重复行的 INSERT、多行临时表、json 数组的聚合、日期列的类型) 由各自的实现提供，
+/// SQLite 的实现见 [`sqlite_ops`](super::sqlite_ops)。
+///
+/// 调用都发生在具体的数据库类型上，例如 `MySql::query_essay(&pool, eid)`。
+/// 泛型代码中 sqlx 0.7 的 `QueryBuilder` 无法执行 (参数的生命周期和 builder 自身的借用绑在一起)，
+/// 所以先拼出带占位符的 SQL，再按顺序 `bind`
+pub(crate) trait Dialect: Database
+where
+    for<'c> &'c mut <Self as Database>::Connection: Executor<'c, Database = Self>,
+    for<'q> <Self as HasArguments<'q>>::Arguments: IntoArguments<'q, Self>,
+    for<'r> &'r str: ColumnIndex<<Self as Database>::Row>,
+    usize: ColumnIndex<<Self as Database>::Row>,
+    for<'q> &'q str: Encode<'q, Self> + Type<Self>,
+    for<'q> Option<&'q str>: Encode<'q, Self> + Type<Self>,
+    for<'q> String: Encode<'q, Self> + Decode<'q, Self> + Type<Self>,
+    for<'q> bool: Encode<'q, Self> + Decode<'q, Self> + Type<Self>,
+    for<'q> f64: Encode<'q, Self> + Decode<'q, Self> + Type<Self>,
+    for<'q> i64: Encode<'q, Self> + Decode<'q, Self> + Type<Self>,
+    for<'q> u32: Encode<'q, Self> + Decode<'q, Self> + Type<Self>,
+{
+    /// 遇到重复的唯一键时跳过这一行的 INSERT
+    const INSERT_IGNORE: &'static str;
+
+    /// 读取 date, publish_at 这类日期时间列，格式为 [`DATE_FORMAT`]
+    fn get_datetime(row: &Self::Row, column: &str) -> Option<String>;
+
+    /// `n` 行 (eid, name) 组成、列名为 eid 和 name 的子查询，每行按顺序绑定 eid 和 name
+    fn link_rows(n: usize) -> String;
+
+    /// 文章 `e` 的 tag 或 category 名字按 id 排序组成的 json 数组，没有时为 `[]`
+    fn taxonomy_json(taxonomy: &Taxonomy) -> String;
+
+    /// 得到数据库中所有已经发布的文章的最后保存时间
+    async fn query_essays_last_save_time(
+        pool: &Pool<Self>,
+    ) -> Result<HashMap<String, f64>> {
+        let rows: Vec<(String, f64)> = sqlx::query_as(&format!(
+            r#"
+SELECT eid, last_save_time FROM essays WHERE {PUBLISHED}
+            "#
+        ))
+        .bind(now_date())
+        .fetch_all(pool)
+        .await?;
+        Ok(rows.into_iter().collect())
+    }
+
+    /// 得到数据库中所有文章的标题和源文件 hash，同步时用来生成变更计划。
+    /// 还没有记录 hash 的文章 hash 为 `None`
+    async fn query_essays_sync_state(
+        pool: &Pool<Self>,
+    ) -> Result<HashMap<String, (String, Option<String>)>> {
+        let rows: Vec<(String, String, Option<String>)> = sqlx::query_as(
+            r#"
+SELECT eid, title, content_hash FROM essays
+            "#
+        )
+        .fetch_all(pool)
+        .await?;
+        Ok(rows
+            .into_iter()
+            .map(|(eid, title, content_hash)| (eid, (title, content_hash)))
+            .collect())
+    }
+
+    /// 根据文章的 eid 得到完整的文章以及它的最后保存时间，文章不存在时返回 `None`。
+    /// 包括还没有发布的文章，是否公开由调用者根据 [`Essay::is_published`] 决定
+    async fn query_essay(
+        pool: &Pool<Self>,
+        eid: &str,
+    ) -> Result<Option<(Essay, f64)>> {
+        let row = sqlx::query(
+            r#"
+SELECT eid, title, date, brief, draft, publish_at, content, toc, last_save_time
 FROM essays
 WHERE eid = ?
-        "#,
-        eid,
-    )
-    .fetch_one(pool)
-    .await?)
-}
+            "#
+        )
+        .bind(eid)
+        .fetch_optional(pool)
+        .await?;
 
-/// 根据文章的 eid 得到该文章的 tags
-pub async fn query_essay_tags(
-    pool: &Pool<MySql>,
-    eid: &str,
-) -> Result<Vec<String>> {
-    Ok(
-        sqlx::query_scalar!(
-        r#"
-SELECT ts.tag_name
-FROM essay_tag et
-JOIN tag_set ts ON et.tag_id = ts.id
-WHERE et.eid = ?
-        "#,
-        eid,
-    )
-    .fetch_all(pool)
-    .await?)
-    
-}
+        match row {
+            Some(row) => {
+                let mut taxonomies = Self::query_taxonomies(pool, std::slice::from_ref(&row)).await?;
+                Ok(Some(Self::essay_from_row(&row, &mut taxonomies)?))
+            },
+            None => Ok(None),
+        }
+    }
 
-/// 根据文章的 eid 得到该文章的 categories
-pub async fn query_essay_categories(
-    pool: &Pool<MySql>,
-    eid: &str
-) -> Result<Vec<String>> {
-    let res = sqlx::query_scalar!(
-        r#"
-SELECT cs.category_name
-FROM essay_category ec
-JOIN category_set cs ON ec.category_id = cs.id
-WHERE ec.eid = ?
-        "#,
-        eid,
-    )
-    .fetch_all(pool)
-    .await?;
-    
-    Ok(res)
-}
+    /// 按 `query` 过滤、分页、排序得到已经发布的文章的 info，同时返回满足过滤条件的文章总数
+    async fn query_essay_info_page(
+        pool: &Pool<Self>,
+        query: &EssayQuery,
+    ) -> Result<(Vec<EssayInfo>, u64)> {
+        let now = now_date();
+        let (filter, values) = essay_filter(query, &now);
+        let sql = format!(
+            r#"
+SELECT COUNT(*) FROM essays {filter}
+            "#
+        );
+        let mut count_query = sqlx::query_scalar(&sql);
+        for value in &values {
+            count_query = count_query.bind(*value);
+        }
+        let total: i64 = count_query
+            .fetch_one(pool)
+            .await?;
+
+        let sql = format!(
+            r#"
+SELECT eid, title, date, brief, draft, publish_at FROM essays {filter} {ESSAY_ORDER}
+            "#,
+            ESSAY_ORDER = essay_order(query),
+        );
+        let mut page_query = sqlx::query(&sql);
+        for value in &values {
+            page_query = page_query.bind(*value);
+        }
+        let rows = page_query
+            .bind(query.per_page)
+            .bind(query.offset() as i64)
+            .fetch_all(pool)
+            .await?;
+
+        let mut taxonomies = Self::query_taxonomies(pool, &rows).await?;
+        let res = rows.iter().map(|row| Self::essay_info_from_row(row, &mut taxonomies)).collect();
+        Ok((res, total as u64))
+    }
+
+    /// 和 `query_essay_info_page` 相同的过滤、分页、排序，但返回带内容的完整文章以及最后保存时间
+    async fn query_essay_page(
+        pool: &Pool<Self>,
+        query: &EssayQuery,
+    ) -> Result<Vec<(Essay, f64)>> {
+        let now = now_date();
+        let (filter, values) = essay_filter(query, &now);
+        let sql = format!(
+            r#"
+SELECT eid, title, date, brief, draft, publish_at, content, toc, last_save_time FROM essays {filter} {ESSAY_ORDER}
+            "#,
+            ESSAY_ORDER = essay_order(query),
+        );
+        let mut page_query = sqlx::query(&sql);
+        for value in &values {
+            page_query = page_query.bind(*value);
+        }
+        let rows = page_query
+            .bind(query.per_page)
+            .bind(query.offset() as i64)
+            .fetch_all(pool)
+            .await?;
+
+        let mut taxonomies = Self::query_taxonomies(pool, &rows).await?;
+        rows.iter().map(|row| Self::essay_from_row(row, &mut taxonomies)).collect()
+    }
+
+    /// 得到已经发布的文章用到的所有 tag 或 category 以及每个名字下的文章数
+    async fn query_term_counts(
+        pool: &Pool<Self>,
+        taxonomy: &Taxonomy,
+    ) -> Result<Vec<TermCount>> {
+        // 表名和列名都来自常量，拼接进 SQL 是安全的
+        let rows: Vec<(String, i64)> = sqlx::query_as(&format!(
+            r#"
+SELECT s.{} AS name, COUNT(l.eid) AS count
+FROM {} s
+JOIN {} l ON l.{} = s.id
+JOIN essays e ON e.eid = l.eid
+WHERE {PUBLISHED}
+GROUP BY s.id, s.{}
+ORDER BY count DESC, name
+            "#,
+            taxonomy.name_column, taxonomy.set_table, taxonomy.link_table, taxonomy.id_column, taxonomy.name_column,
+        ))
+        .bind(now_date())
+        .fetch_all(pool)
+        .await?;
+        Ok(rows
+            .into_iter()
+            .map(|(name, count)| TermCount { name, count: count as u64 })
+            .collect())
+    }
+
+    /// 用两条查询得到 `rows` 中所有文章的 tags 和 categories，查询次数和文章数无关
+    async fn query_taxonomies(
+        pool: &Pool<Self>,
+        rows: &[Self::Row],
+    ) -> Result<Taxonomies> {
+        let eids: Vec<String> = rows.iter().map(|row| row.get("eid")).collect();
+        Ok(Taxonomies {
+            tags: Self::query_taxonomy_names(pool, &TAGS, &eids).await?,
+            categories: Self::query_taxonomy_names(pool, &CATEGORIES, &eids).await?,
+        })
+    }
 
-/// 得到所有文章的 info (eid, title, date, brief, tags, categories)
-pub async fn query_essay_info(
-    pool: &Pool<MySql>,
-) -> Result<Vec<EssayInfo>> {
-    let rows = sqlx::query(
-        r#"
-SELECT eid, title, date, brief FROM essays
-        "#
-    )
-    .fetch_all(pool)
-    .await?;
-
-    let mut res = Vec::new();
-    for row in rows {
+    /// 得到 `eids` 中每篇文章的 tag 或 category 名字
+    async fn query_taxonomy_names(
+        pool: &Pool<Self>,
+        taxonomy: &Taxonomy,
+        eids: &[String],
+    ) -> Result<HashMap<String, Vec<String>>> {
+        let mut res: HashMap<String, Vec<String>> = HashMap::new();
+        if eids.is_empty() {
+            return Ok(res);
+        }
+        // 表名和列名都来自常量，拼接进 SQL 是安全的
+        let sql = format!(
+            r#"
+SELECT l.eid, s.{}
+FROM {} l
+JOIN {} s ON l.{} = s.id
+WHERE l.eid IN ({})
+ORDER BY s.id
+            "#,
+            taxonomy.name_column, taxonomy.link_table, taxonomy.set_table, taxonomy.id_column, placeholders(eids.len()),
+        );
+        let mut names_query = sqlx::query_as(&sql);
+        for eid in eids {
+            names_query = names_query.bind(eid.as_str());
+        }
+        let rows: Vec<(String, String)> = names_query
+            .fetch_all(pool)
+            .await?;
+        for (eid, name) in rows {
+            res.entry(eid).or_default().push(name);
+        }
+        Ok(res)
+    }
+
+    fn essay_info_from_row(
+        row: &Self::Row,
+        taxonomies: &mut Taxonomies,
+    ) -> EssayInfo {
         let eid: String = row.get("eid");
         let title: String = row.get("title");
-        let date: NaiveDateTime = row.get("date");
-        let date = date.format("%Y-%m-%d %H:%M:%S").to_string();
+        let date = Self::get_datetime(row, "date").unwrap_or_default();
         let brief: String = row.get("brief");
-        let tags: Vec<String> = query_essay_tags(pool, &eid).await?;
-        let categories: Vec<String> = query_essay_categories(pool, &eid).await?;
-        res.push( EssayInfo::new( eid, title, date, categories, tags, brief) );
+        let tags = taxonomies.tags.remove(&eid).unwrap_or_default();
+        let categories = taxonomies.categories.remove(&eid).unwrap_or_default();
+        let mut info = EssayInfo::new(eid, title, date, categories, tags, brief);
+        info.draft = row.get("draft");
+        info.publish_at = Self::get_datetime(row, "publish_at");
+        info
     }
-    Ok(res)
-}
-/// 向数据库中添加一篇文章
-pub async fn insert_essay(
-    pool: &Pool<MySql>,
-    essay: &Essay,
-    current_time: f64,
-) -> Result<()> {
-
-    insert_essay_info(pool, essay, current_time).await?;
-    insert_essay_tags(pool, essay).await?;
-    insert_essay_categories(pool, essay).await?;
-
-    Ok(())
-}
 
-async fn insert_essay_info(
-    pool: &Pool<MySql>,
-    essay: &Essay,
-    current_time: f64,
-) -> Result<()>{
-    sqlx::query!(
-        r#"
-INSERT INTO essays (eid, title, date, brief, content, last_save_time) 
-VALUES (?, ?, ?, ?, ?, ?)
-        "#,
-        essay.eid,
-        essay.title,
-        essay.date,
-        essay.brief,
-        essay.content,
-        current_time,
-    )
-    .execute(pool)
-    .await?;
-    Ok(())
-}
+    /// 由包含 content, toc, last_save_time 列的行得到完整的文章以及最后保存时间
+    fn essay_from_row(
+        row: &Self::Row,
+        taxonomies: &mut Taxonomies,
+    ) -> Result<(Essay, f64)> {
+        let mut essay = Essay::from(Self::essay_info_from_row(row, taxonomies));
+        let content: Option<String> = row.get("content");
+        essay.content = content.unwrap_or_default();
+        let toc: Option<String> = row.get("toc");
+        essay.toc = match toc {
+            Some(toc) => serde_json::from_str(&toc)?,
+            None => Vec::new(),
+        };
+        let last_save_time: f64 = row.get("last_save_time");
+        Ok((essay, last_save_time))
+    }
+
+    /// 全文搜索已经发布的文章，返回按相关度排序的前 `limit` 条结果以及命中的文章总数
+    async fn search_essays(
+        pool: &Pool<Self>,
+        q: &str,
+        limit: u32,
+    ) -> Result<(Vec<SearchHit>, u64)> {
+        let terms = search::query_terms(q);
+        if terms.is_empty() {
+            return Ok((Vec::new(), 0));
+        }
+
+        let now = now_date();
+        let total: i64 = sqlx::query_scalar(&format!(
+            r#"
+SELECT COUNT(*) FROM essays WHERE {PUBLISHED}
+            "#
+        ))
+        .bind(now.as_str())
+        .fetch_one(pool)
+        .await?;
+
+        let sql = format!(
+            r#"
+SELECT t.eid, t.term, t.weight
+FROM essay_term t
+JOIN essays e ON e.eid = t.eid
+WHERE t.term IN ({}) AND {PUBLISHED}
+            "#,
+            placeholders(terms.len()),
+        );
+        let mut hits_query = sqlx::query_as(&sql);
+        for term in &terms {
+            hits_query = hits_query.bind(term.as_str());
+        }
+        let hits: Vec<(String, String, f64)> = hits_query
+            .bind(now.as_str())
+            .fetch_all(pool)
+            .await?;
+
+        let ranked = search::rank(&hits, total as u64);
+        let count = ranked.len() as u64;
+        let ranked: Vec<_> = ranked.into_iter().take(limit as usize).collect();
+        if ranked.is_empty() {
+            return Ok((Vec::new(), count));
+        }
+
+        let sql = format!(
+            r#"
+SELECT eid, title, date, brief, draft, publish_at, content FROM essays WHERE eid IN ({})
+            "#,
+            placeholders(ranked.len()),
+        );
+        let mut essays_query = sqlx::query(&sql);
+        for (eid, _) in &ranked {
+            essays_query = essays_query.bind(eid.as_str());
+        }
+        let rows = essays_query
+            .fetch_all(pool)
+            .await?;
+
+        let mut taxonomies = Self::query_taxonomies(pool, &rows).await?;
+        let mut found = HashMap::new();
+        for row in rows {
+            let info = Self::essay_info_from_row(&row, &mut taxonomies);
+            let content: Option<String> = row.get("content");
+            let text = search::strip_html(&content.unwrap_or_default());
+            let text = if text.is_empty() { info.brief.clone() } else { text };
+            found.insert(info.eid.clone(), (info, text));
+        }
 
-async fn insert_essay_tags(
-    pool: &Pool<MySql>,
-    essay: &Essay
-) -> Result<()> {
-    let tag_set = query_tag_set(pool).await?;
-    for tag in &essay.tags {
-        if !tag_set.contains(tag) {
-            insert_tag(pool, tag).await?;
+        let mut res = Vec::new();
+        for (eid, score) in ranked {
+            if let Some((info, text)) = found.remove(&eid) {
+                let snippet = search::snippet(&text, &terms);
+                res.push(SearchHit { info, score, snippet });
+            }
         }
-        insert_eaasy_tag(pool, &essay.eid, tag).await?;
+        Ok((res, count))
+    }
+
+    /// 得到文章的所有历史版本，最新的在前
+    async fn query_revisions(
+        pool: &Pool<Self>,
+        eid: &str,
+    ) -> Result<Vec<RevisionInfo>> {
+        let rows = sqlx::query(
+            r#"
+SELECT eid, revision, title, draft, publish_at, saved_at, archived_at
+FROM essay_revision
+WHERE eid = ?
+ORDER BY revision DESC
+            "#
+        )
+        .bind(eid)
+        .fetch_all(pool)
+        .await?;
+        Ok(rows.iter().map(Self::revision_info_from_row).collect())
+    }
+
+    /// 得到文章的某个历史版本，不存在时返回 `None`
+    async fn query_revision(
+        pool: &Pool<Self>,
+        eid: &str,
+        revision: u32,
+    ) -> Result<Option<Revision>> {
+        let row = sqlx::query(
+            r#"
+SELECT eid, revision, title, date, brief, draft, publish_at, content, categories, tags, saved_at, archived_at
+FROM essay_revision
+WHERE eid = ? AND revision = ?
+            "#
+        )
+        .bind(eid)
+        .bind(revision)
+        .fetch_optional(pool)
+        .await?;
+
+        let Some(row) = row else {
+            return Ok(None);
+        };
+        let content: Option<String> = row.get("content");
+        let categories: String = row.get("categories");
+        let tags: String = row.get("tags");
+        Ok(Some(Revision {
+            info: Self::revision_info_from_row(&row),
+            date: Self::get_datetime(&row, "date").unwrap_or_default(),
+            brief: row.get("brief"),
+            categories: serde_json::from_str(&categories)?,
+            tags: serde_json::from_str(&tags)?,
+            content: content.unwrap_or_default(),
+        }))
     }
-    Ok(())
-}
 
-async fn insert_essay_categories(
-    pool: &Pool<MySql>,
-    essay: &Essay
-) -> Result<()> {
-    let category_set = query_category_set(pool).await?;
-    for category in &essay.categories {
-        if !category_set.contains(category) {
-            insert_category(pool, category).await?;
+    fn revision_info_from_row(row: &Self::Row) -> RevisionInfo {
+        RevisionInfo {
+            eid: row.get("eid"),
+            revision: row.get("revision"),
+            title: row.get("title"),
+            saved_at: row.get("saved_at"),
+            archived_at: row.get("archived_at"),
+            draft: row.get("draft"),
+            publish_at: Self::get_datetime(row, "publish_at"),
         }
-        insert_eaasy_category(pool, &essay.eid, category).await?;
     }
-    Ok(())
-}
 
-async fn insert_eaasy_tag(
-    pool: &Pool<MySql>,
-    eid: &str,
-    tag: &str,
-) -> Result<()> {
-    let tag_id = sqlx::query_scalar!(
-        r#"
-SELECT id FROM tag_set WHERE tag_name = ?
-        "#,
-        tag
-    )
-    .fetch_one(pool)
-    .await?;
-
-    sqlx::query!(
-        r#"
-INSERT INTO essay_tag (eid, tag_id) VALUES (?, ?)
-        "#,
-        eid,
-        tag_id
-    )
-    .execute(pool)
-    .await?;
-
-    Ok(())
-}
+    /// 写入 essays 表中的一行，不包括 tags, categories 和索引词
+    async fn insert_essay_info(
+        conn: &mut Self::Connection,
+        essay: &Essay,
+        content_hash: &str,
+        current_time: f64,
+    ) -> Result<()> {
+        let toc = serde_json::to_string(&essay.toc)?;
+        sqlx::query(
+            r#"
+INSERT INTO essays (eid, title, date, brief, draft, publish_at, content, toc, content_hash, version_hash, last_save_time)
+VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+            "#
+        )
+        .bind(essay.eid.as_str())
+        .bind(essay.title.as_str())
+        .bind(essay.date.as_str())
+        .bind(essay.brief.as_str())
+        .bind(essay.draft)
+        .bind(essay.publish_at.as_deref())
+        .bind(essay.content.as_str())
+        .bind(toc)
+        .bind(content_hash)
+        .bind(essay.version_hash())
+        .bind(current_time)
+        .execute(&mut *conn)
+        .await?;
+        Ok(())
+    }
 
-async fn insert_eaasy_category(
-    pool: &Pool<MySql>,
-    eid: &str,
-    category: &str,
-) -> Result<()> {
-    let category_id: u32 = sqlx::query_scalar!(
-        r#"
-SELECT id FROM category_set WHERE category_name = ?
-        "#,
-        category
-    )
-    .fetch_one(pool)
-    .await?;
-
-    sqlx::query!(
-        r#"
-INSERT INTO essay_category (eid, category_id) VALUES (?, ?)
-        "#,
-        eid,
-        category_id
-    )
-    .execute(pool)
-    .await?;
-
-    Ok(())
-}
+    /// 更新 essays 表中的一行，不包括 tags, categories 和索引词
+    async fn update_essay_info(
+        conn: &mut Self::Connection,
+        essay: &Essay,
+        content_hash: &str,
+        current_time: f64,
+    ) -> Result<()> {
+        let toc = serde_json::to_string(&essay.toc)?;
+        sqlx::query(
+            r#"
+UPDATE essays
+SET title = ?, date = ?, brief = ?, draft = ?, publish_at = ?, content = ?, toc = ?, content_hash = ?, version_hash = ?, last_save_time = ?
+WHERE eid = ?
+            "#
+        )
+        .bind(essay.title.as_str())
+        .bind(essay.date.as_str())
+        .bind(essay.brief.as_str())
+        .bind(essay.draft)
+        .bind(essay.publish_at.as_deref())
+        .bind(essay.content.as_str())
+        .bind(toc)
+        .bind(content_hash)
+        .bind(essay.version_hash())
+        .bind(current_time)
+        .bind(essay.eid.as_str())
+        .execute(&mut *conn)
+        .await?;
+        Ok(())
+    }
 
-async fn insert_tag(
-    pool: &Pool<MySql>,
-    tag: &str,
-) -> Result<()> {
-    sqlx::query!(
-        r#"
-INSERT INTO tag_set (tag_name) VALUES (?)
-        "#,
-        tag
-    )
-    .execute(pool)
-    .await?
-    .last_insert_id();
-    
-    Ok(())
-}
+    /// 批量写入一批文章的 tags, categories 和全文索引词，语句数量和文章数无关。
+    /// 文章本身必须已经写入 essays 表
+    async fn insert_essay_links(
+        conn: &mut Self::Connection,
+        essays: &[&Essay],
+    ) -> Result<()> {
+        let tags: Vec<_> = essays
+            .iter()
+            .flat_map(|essay| essay.tags.iter().map(|tag| (essay.eid.as_str(), tag.as_str())))
+            .collect();
+        Self::insert_taxonomy_links(conn, &TAGS, &tags).await?;
+        let categories: Vec<_> = essays
+            .iter()
+            .flat_map(|essay| essay.categories.iter().map(|category| (essay.eid.as_str(), category.as_str())))
+            .collect();
+        Self::insert_taxonomy_links(conn, &CATEGORIES, &categories).await?;
+        Self::insert_essay_terms(conn, essays).await?;
+        Ok(())
+    }
 
-async fn insert_category(
-    pool: &Pool<MySql>,
-    category: &str,
-) -> Result<()> {
-    sqlx::query!(
-        r#"
-INSERT INTO category_set (category_name) VALUES (?)
-        "#,
-        category
-    )
-    .execute(pool)
-    .await?;
-    
-    Ok(())
-}
+    /// 写入 (eid, 名字) 关联：先用一条语句补齐 `set_table` 中缺少的名字，
+    /// 再用一条 INSERT ... SELECT 按名字查出 id 写入所有关联，名字的比较使用数据库的排序规则
+    async fn insert_taxonomy_links(
+        conn: &mut Self::Connection,
+        taxonomy: &Taxonomy,
+        links: &[(&str, &str)],
+    ) -> Result<()> {
+        let names: BTreeSet<&str> = links.iter().map(|(_, name)| *name).collect();
+        let names: Vec<_> = names.into_iter().collect();
+        // 表名和列名都来自常量，拼接进 SQL 是安全的
+        for chunk in names.chunks(CHUNK_SIZE) {
+            let sql = format!(
+                r#"
+{} INTO {} ({}) VALUES {}
+                "#,
+                Self::INSERT_IGNORE, taxonomy.set_table, taxonomy.name_column, value_rows(chunk.len(), 1),
+            );
+            let mut insert = sqlx::query(&sql);
+            for name in chunk {
+                insert = insert.bind(*name);
+            }
+            insert
+                .execute(&mut *conn)
+                .await?;
+        }
 
-async fn query_tag_set(
-    pool: &Pool<MySql>,
-) -> Result<HashSet<String>> {
-    let rows = sqlx::query(
-        r#"
-SELECT tag_name FROM tag_set
-        "#
-    )
-    .fetch_all(pool)
-    .await?;
-    let mut res = HashSet::new();
-    for row in rows {
-        res.insert(row.get("tag_name"));
-    }
-    Ok(res)
+        for chunk in links.chunks(CHUNK_SIZE) {
+            let sql = format!(
+                r#"
+INSERT INTO {} (eid, {})
+SELECT DISTINCT l.eid, s.id
+FROM {} s
+JOIN ({}) l ON s.{} = l.name
+                "#,
+                taxonomy.link_table, taxonomy.id_column, taxonomy.set_table, Self::link_rows(chunk.len()), taxonomy.name_column,
+            );
+            let mut insert = sqlx::query(&sql);
+            for (eid, name) in chunk {
+                insert = insert.bind(*eid).bind(*name);
+            }
+            insert
+                .execute(&mut *conn)
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// 写入文章的全文索引词
+    async fn insert_essay_terms(
+        conn: &mut Self::Connection,
+        essays: &[&Essay],
+    ) -> Result<()> {
+        let terms: Vec<(&str, String, f64)> = essays
+            .iter()
+            .flat_map(|essay| {
+                search::essay_terms(essay)
+                    .into_iter()
+                    .map(|(term, weight)| (essay.eid.as_str(), term, weight))
+            })
+            .collect();
+        for chunk in terms.chunks(CHUNK_SIZE) {
+            let sql = format!(
+                r#"
+INSERT INTO essay_term (eid, term, weight) VALUES {}
+                "#,
+                value_rows(chunk.len(), 3),
+            );
+            let mut insert = sqlx::query(&sql);
+            for (eid, term, weight) in chunk {
+                insert = insert.bind(*eid).bind(term.as_str()).bind(*weight);
+            }
+            insert
+                .execute(&mut *conn)
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// 在更新之前把文章当前的版本连同 tags 和 categories 存入 essay_revision，
+    /// 版本号接着该文章已有的最大版本号。必须在删除 tags 和 categories 之前调用。
+    /// 保存的 version_hash 和新版本相同 (只改了草稿、定时发布或者源文件中不影响内容的部分) 时不存
+    async fn archive_essays(
+        conn: &mut Self::Connection,
+        essays: &[&Essay],
+        current_time: f64,
+    ) -> Result<()> {
+        for chunk in essays.chunks(CHUNK_SIZE) {
+            let versions: Vec<_> = chunk.iter().map(|essay| (essay.eid.as_str(), essay.version_hash())).collect();
+            // 子查询的 name 列是新版本的 version_hash
+            let sql = format!(
+                r#"
+INSERT INTO essay_revision (eid, revision, title, date, brief, draft, publish_at, content, categories, tags, saved_at, archived_at)
+SELECT e.eid,
+    COALESCE((SELECT MAX(r.revision) FROM essay_revision r WHERE r.eid = e.eid), 0) + 1,
+    e.title, e.date, e.brief, e.draft, e.publish_at, e.content, {}, {}, e.last_save_time, ?
+FROM essays e
+JOIN ({}) v ON e.eid = v.eid
+WHERE e.version_hash IS NULL OR e.version_hash <> v.name
+                "#,
+                Self::taxonomy_json(&CATEGORIES), Self::taxonomy_json(&TAGS), Self::link_rows(chunk.len()),
+            );
+            let mut insert = sqlx::query(&sql).bind(current_time);
+            for (eid, version_hash) in &versions {
+                insert = insert.bind(*eid).bind(version_hash.as_str());
+            }
+            insert
+                .execute(&mut *conn)
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// 批量删除一批文章的 tags, categories 和全文索引词
+    async fn delete_essay_links(
+        conn: &mut Self::Connection,
+        eids: &[&str],
+    ) -> Result<()> {
+        for table in [TAGS.link_table, CATEGORIES.link_table, "essay_term"] {
+            Self::delete_by_eids(conn, table, eids).await?;
+        }
+        Ok(())
+    }
+
+    /// 删除 `table` 中 eid 属于 `eids` 的行，`table` 只能是常量
+    async fn delete_by_eids(
+        conn: &mut Self::Connection,
+        table: &str,
+        eids: &[&str],
+    ) -> Result<()> {
+        for chunk in eids.chunks(CHUNK_SIZE) {
+            let sql = format!(
+                r#"
+DELETE FROM {table} WHERE eid IN ({})
+                "#,
+                placeholders(chunk.len()),
+            );
+            let mut delete = sqlx::query(&sql);
+            for eid in chunk {
+                delete = delete.bind(*eid);
+            }
+            delete
+                .execute(&mut *conn)
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// 批量删除文章以及它们的 tags, categories, 全文索引词和历史版本
+    async fn delete_essays(
+        conn: &mut Self::Connection,
+        eids: &[&str],
+    ) -> Result<()> {
+        Self::delete_essay_links(conn, eids).await?;
+        Self::delete_by_eids(conn, "essay_revision", eids).await?;
+        Self::delete_by_eids(conn, "essays", eids).await?;
+        Ok(())
+    }
 }
 
-async fn query_category_set(
-    pool: &Pool<MySql>,
-) -> Result<HashSet<String>> {
-    let rows = sqlx::query(
-        r#"
-SELECT category_name FROM category_set
-        "#
-    )
-    .fetch_all(pool)
-    .await?;
-    let mut res = HashSet::new();
-    for row in rows {
-        res.insert(row.get("category_name"));
-    }
-    Ok(res)
+/// ORDER BY 和 LIMIT 子句，按顺序绑定 `per_page` 和偏移量
+fn essay_order(query: &EssayQuery) -> String {
+    // 排序字段和方向都来自枚举，拼接进 SQL 是安全的
+    format!("ORDER BY {} {}, eid LIMIT ? OFFSET ?", query.sort.column(), query.order.keyword())
 }
 
-pub async fn update_essay(
-    pool: &Pool<MySql>,
-    essay: &Essay,
-    current_time: f64,
-) -> Result<()> {
-    delete_essay_tags(pool, &essay.eid).await?;
-    delete_essay_categories(pool, &essay.eid).await?;
-    update_essay_info(pool, essay, current_time).await?;
-    insert_essay_categories(pool, essay).await?;
-    insert_essay_tags(pool, essay).await?;
-
-    Ok(())
+/// WHERE 子句，只保留在 `now` 时已经发布 (见 [`PUBLISHED`]) 并且满足 `query` 的 tags 和 categories 的文章。
+/// 同时返回按顺序绑定的值
+fn essay_filter<'a>(query: &'a EssayQuery, now: &'a str) -> (String, Vec<&'a str>) {
+    let joiner = match query.mode {
+        MatchMode::All => " AND ",
+        MatchMode::Any => " OR ",
+    };
+    let conditions: Vec<_> = query
+        .tags
+        .iter()
+        .map(|tag| ("eid IN (SELECT et.eid FROM essay_tag et JOIN tag_set ts ON et.tag_id = ts.id WHERE ts.tag_name = ?)", tag))
+        .chain(query.categories.iter().map(|category| {
+            ("eid IN (SELECT ec.eid FROM essay_category ec JOIN category_set cs ON ec.category_id = cs.id WHERE cs.category_name = ?)", category)
+        }))
+        .collect();
+    let mut sql = format!("WHERE {PUBLISHED}");
+    let mut values = vec![now];
+    if !conditions.is_empty() {
+        let conditions: Vec<_> = conditions
+            .into_iter()
+            .map(|(condition, value)| {
+                values.push(value.as_str());
+                condition
+            })
+            .collect();
+        sql.push_str(&format!(" AND ({})", conditions.join(joiner)));
+    }
+    (sql, values)
 }
 
-async fn delete_essay_tags(
-    pool: &Pool<MySql>,
-    eid: &str,
-) -> Result<()> {
-    sqlx::query!(
-        r#"
-DELETE FROM essay_tag WHERE eid = ?
-        "#,
-        eid
-    )
-    .execute(pool)
-    .await?;
-    Ok(())
+/// `n` 个以逗号分隔的占位符
+pub(crate) fn placeholders(n: usize) -> String {
+    vec!["?"; n].join(", ")
 }
 
-async fn delete_essay_categories(
-    pool: &Pool<MySql>,
-    eid: &str,
-) -> Result<()> {
-    sqlx::query!(
-        r#"
-DELETE FROM essay_category WHERE eid = ?
-        "#,
-        eid
-    )
-    .execute(pool)
-    .await?;
-    Ok(())
+/// `rows` 行、每行 `columns` 个占位符的 VALUES 列表
+pub(crate) fn value_rows(rows: usize, columns: usize) -> String {
+    vec![format!("({})", placeholders(columns)); rows].join(", ")
 }
 
-async fn update_essay_info(
-    pool: &Pool<MySql>,
-    essay: &Essay,
-    current_time: f64,
-) -> Result<()> {
-    sqlx::query!(
-        r#"
-UPDATE essays 
-SET title = ?, date = ?, brief = ?, content = ?, last_save_time = ?
-WHERE eid = ?
-        "#,
-        essay.title,
-        essay.date,
-        essay.brief,
-        essay.content,
-        current_time,
-        essay.eid,
-    )
-    .execute(pool)
-    .await?;
-    Ok(())
+impl Dialect for MySql {
+    const INSERT_IGNORE: &'static str = "INSERT IGNORE";
+
+    fn get_datetime(row: &MySqlRow, column: &str) -> Option<String> {
+        let datetime: Option<NaiveDateTime> = row.get(column);
+        datetime.map(|datetime| datetime.format(DATE_FORMAT).to_string())
+    }
+
+    fn link_rows(n: usize) -> String {
+        // 不同版本的 MySQL 和 MariaDB 中 VALUES 子查询的列名不一致，用 UNION ALL 拼出带列名的子查询
+        let mut rows = vec!["SELECT ? AS eid, ? AS name"];
+        rows.resize(n, "SELECT ?, ?");
+        rows.join(" UNION ALL ")
+    }
+
+    fn taxonomy_json(taxonomy: &Taxonomy) -> String {
+        // 表名和列名都来自常量，拼接进 SQL 是安全的
+        format!(
+            "COALESCE((SELECT JSON_ARRAYAGG(s.{} ORDER BY s.id) FROM {} l JOIN {} s ON l.{} = s.id WHERE l.eid = e.eid), '[]')",
+            taxonomy.name_column, taxonomy.link_table, taxonomy.set_table, taxonomy.id_column,
+        )
+    }
 }
 
-pub async fn delete_essay(
-    pool: &Pool<MySql>,
-    eid: &str,
-) -> Result<()> {
-    delete_essay_categories(pool, eid).await?;
-    delete_essay_tags(pool, eid).await?;
-    sqlx::query!(
-        r#"
-DELETE FROM essays WHERE eid = ?
-        "#,
-        eid
-    )
-    .execute(pool)
-    .await?;
-    Ok(())
-}
\ No newline at end of file
+#[async_trait]
+impl SyncWrite for MySqlConnection {
+    async fn delete_essays(&mut self, eids: &[&str]) -> Result<()> {
+        MySql::delete_essays(self, eids).await
+    }
+
+    async fn archive_essays(&mut self, essays: &[&Essay], current_time: f64) -> Result<()> {
+        MySql::archive_essays(self, essays, current_time).await
+    }
+
+    async fn delete_essay_links(&mut self, eids: &[&str]) -> Result<()> {
+        MySql::delete_essay_links(self, eids).await
+    }
+
+    async fn insert_essay_info(&mut self, essay: &Essay, content_hash: &str, current_time: f64) -> Result<()> {
+        MySql::insert_essay_info(self, essay, content_hash, current_time).await
+    }
+
+    async fn update_essay_info(&mut self, essay: &Essay, content_hash: &str, current_time: f64) -> Result<()> {
+        MySql::update_essay_info(self, essay, content_hash, current_time).await
+    }
+
+    async fn insert_essay_links(&mut self, essays: &[&Essay]) -> Result<()> {
+        MySql::insert_essay_links(self, essays).await
+    }
+}
@@ -1,20 +1,166 @@
-use std::time::Duration;
+use std::{collections::HashSet, str::FromStr, time::Duration};
 
-use anyhow::Result;
+use anyhow::{bail, Context, Result};
 use sqlx::{
+    Database,
     MySql,
+    MySqlConnection,
     Pool,
+    Sqlite,
+    migrate::{Migrate, Migrator},
     mysql::MySqlPoolOptions,
+    sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePoolOptions},
 };
 
-use crate::DATABASE_URL;
+use uuid::Uuid;
+
+use crate::{now, DATABASE_URL};
 
 pub async fn build_pool() -> Result<Pool<MySql>> {
-    Ok(MySqlPoolOptions::new()
+    build_mysql_pool(&DATABASE_URL).await
+}
+
+pub async fn build_mysql_pool(url: &str) -> Result<Pool<MySql>> {
+    MySqlPoolOptions::new()
         .max_connections(20)
         .acquire_timeout(Duration::from_secs(3))
-        .connect(&DATABASE_URL)
+        .connect(url)
         .await
-        .expect("can't connect database")
+        .context("can't connect database")
+}
+
+/// 连接 SQLite 数据库，文件不存在时创建。
+/// `sqlite::memory:` 的内存数据库在最后一个连接关闭时消失，所以只用一个不会过期的连接
+pub async fn build_sqlite_pool(url: &str) -> Result<Pool<Sqlite>> {
+    let options = SqliteConnectOptions::from_str(url)?
+        .create_if_missing(true)
+        .journal_mode(SqliteJournalMode::Wal);
+    let pool_options = match url.contains(":memory:") || url.contains("mode=memory") {
+        true => SqlitePoolOptions::new()
+            .max_connections(1)
+            .idle_timeout(None)
+            .max_lifetime(None),
+        false => SqlitePoolOptions::new().max_connections(20),
+    };
+    Ok(pool_options
+        .acquire_timeout(Duration::from_secs(3))
+        .connect_with(options)
+        .await?)
+}
+
+/// 编译进程序的 MySQL schema 迁移，见 `push_server/migrations/mysql`。
+/// 已经执行过的迁移记录在数据库的 `_sqlx_migrations` 表中
+pub static MYSQL_MIGRATOR: Migrator = sqlx::migrate!("./migrations/mysql");
+
+/// 编译进程序的 SQLite schema 迁移，见 `push_server/migrations/sqlite`
+pub static SQLITE_MIGRATOR: Migrator = sqlx::migrate!("./migrations/sqlite");
+
+/// 执行 `migrator` 中所有还没有执行过的迁移，返回这次执行的迁移 (版本, 描述)。
+/// 迁移期间 sqlx 持有数据库锁，同时启动的多个程序不会重复执行
+pub async fn migrate<DB>(migrator: &Migrator, pool: &Pool<DB>) -> Result<Vec<(i64, String)>>
+where
+    DB: Database,
+    DB::Connection: Migrate,
+{
+    let mut conn = pool.acquire().await?;
+    conn.ensure_migrations_table().await?;
+    let applied: HashSet<i64> = conn
+        .list_applied_migrations()
+        .await?
+        .into_iter()
+        .map(|migration| migration.version)
+        .collect();
+    // 内存数据库的连接池只有一个连接，先归还再执行迁移
+    drop(conn);
+    migrator.run(pool).await?;
+    Ok(migrator
+        .iter()
+        .filter(|migration| !applied.contains(&migration.version))
+        .map(|migration| (migration.version, migration.description.to_string()))
+        .collect())
+}
+
+/// push_server 同步时持有的数据库命名锁
+pub const SYNC_LOCK: &str = "push_server_sync";
+
+/// 在 `conn` 上获取命名锁，锁被其他连接持有时立即返回错误而不是等待。
+/// 锁跟随连接，连接断开时会被自动释放
+pub async fn acquire_lock(conn: &mut MySqlConnection, name: &str) -> Result<()> {
+    let acquired: Option<i64> = sqlx::query_scalar(
+        r#"
+SELECT GET_LOCK(?, 0)
+        "#
     )
-}
\ No newline at end of file
+    .bind(name)
+    .fetch_one(&mut *conn)
+    .await?;
+    if acquired != Some(1) {
+        bail!("lock `{name}` is held by another connection, is another push_server sync running?");
+    }
+    Ok(())
+}
+
+pub async fn release_lock(conn: &mut MySqlConnection, name: &str) -> Result<()> {
+    sqlx::query(
+        r#"
+SELECT RELEASE_LOCK(?)
+        "#
+    )
+    .bind(name)
+    .execute(&mut *conn)
+    .await?;
+    Ok(())
+}
+
+/// 超过这个时间 (秒) 还没有释放的 SQLite 同步锁视为持有者已经退出，可以被取走
+pub const STALE_LOCK_SECS: f64 = 3600.0;
+
+/// 在 `sync_lock` 表中获取名为 `name` 的锁，返回持有者标识，释放时使用。
+/// 锁被持有时立即返回错误而不是等待。和 MySQL 的命名锁不同，锁不会随连接断开释放，
+/// 持有者异常退出时锁在 [`STALE_LOCK_SECS`] 之后才能被重新获取
+pub async fn acquire_table_lock(pool: &Pool<Sqlite>, name: &str) -> Result<String> {
+    let holder = Uuid::new_v4().to_string();
+    let current_time = now();
+    let acquired = sqlx::query(
+        r#"
+INSERT INTO sync_lock (name, holder, locked_at) VALUES (?, ?, ?)
+ON CONFLICT (name) DO UPDATE SET holder = excluded.holder, locked_at = excluded.locked_at
+WHERE sync_lock.locked_at < ?
+        "#
+    )
+    .bind(name)
+    .bind(&holder)
+    .bind(current_time)
+    .bind(current_time - STALE_LOCK_SECS)
+    .execute(pool)
+    .await?
+    .rows_affected();
+    if acquired != 1 {
+        bail!("lock `{name}` is held by another process, is another push_server sync running?");
+    }
+    Ok(holder)
+}
+
+pub async fn release_table_lock(pool: &Pool<Sqlite>, name: &str, holder: &str) -> Result<()> {
+    sqlx::query(
+        r#"
+DELETE FROM sync_lock WHERE name = ? AND holder = ?
+        "#
+    )
+    .bind(name)
+    .bind(holder)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn unreachable_mysql_is_an_error() {
+        let err = build_mysql_pool("mysql://root@127.0.0.1:1/rusite").await.unwrap_err();
+        assert!(err.to_string().contains("can't connect database"), "{err:#}");
+    }
+}
@@ -0,0 +1,351 @@
+use std::fmt;
+
+use chrono::{FixedOffset, Local, NaiveDateTime, TimeZone};
+use serde::de::DeserializeOwned;
+
+use crate::time::DATE_FORMAT;
+
+/// front matter 的格式，由第一行的分隔符决定
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrontMatterFormat {
+    /// `---` 包围的 YAML
+    Yaml,
+    /// `+++` 包围的 TOML (Hugo)
+    Toml,
+}
+
+impl FrontMatterFormat {
+    fn delimiter(&self) -> &'static str {
+        match self {
+            Self::Yaml => "---",
+            Self::Toml => "+++",
+        }
+    }
+}
+
+/// front matter 反序列化失败，`line` 和 `column` 从 1 开始，是在整个文件中的位置
+#[derive(Debug, Clone, PartialEq)]
+pub struct FrontMatterError {
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+    pub message: String,
+}
+
+impl fmt::Display for FrontMatterError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.line, self.column) {
+            (Some(line), Some(column)) => write!(f, "{} at line {line} column {column}", self.message),
+            (Some(line), None) => write!(f, "{} at line {line}", self.message),
+            _ => write!(f, "{}", self.message),
+        }
+    }
+}
+
+impl std::error::Error for FrontMatterError {}
+
+impl FrontMatterError {
+    fn at_first_line(message: &str) -> Self {
+        Self { line: Some(1), column: None, message: message.to_string() }
+    }
+}
+
+/// 拆分后的 markdown 文件
+#[derive(Debug, Clone, PartialEq)]
+pub struct FrontMatter<'a> {
+    pub format: FrontMatterFormat,
+    /// 分隔符之间的内容
+    pub raw: &'a str,
+    /// front matter 之后的正文，正文中的 `---` 分割线原样保留
+    pub body: &'a str,
+    /// 正文之前的行数，用于把正文中的行号换算回文件中的行号
+    pub body_offset: usize,
+}
+
+impl<'a> FrontMatter<'a> {
+    /// front matter 只能是文件的第一个块：第一行必须是 `---` 或 `+++`，到下一个相同的分隔符为止
+    pub fn split(content: &'a str) -> Result<Self, FrontMatterError> {
+        let content = content.strip_prefix('\u{feff}').unwrap_or(content);
+        let mut lines = content.split_inclusive('\n');
+        let first = lines.next().unwrap_or_default();
+        let format = match first.trim_end() {
+            "---" => FrontMatterFormat::Yaml,
+            "+++" => FrontMatterFormat::Toml,
+            _ => return Err(FrontMatterError::at_first_line(
+                "missing front matter: the first line must be `---` (YAML) or `+++` (TOML)",
+            )),
+        };
+
+        let start = first.len();
+        let mut end = start;
+        for (i, line) in lines.enumerate() {
+            if line.trim_end() == format.delimiter() {
+                return Ok(Self {
+                    format,
+                    raw: &content[start..end],
+                    body: &content[end + line.len()..],
+                    body_offset: i + 2,
+                });
+            }
+            end += line.len();
+        }
+        Err(FrontMatterError::at_first_line(&format!(
+            "front matter is not closed by `{}`",
+            format.delimiter()
+        )))
+    }
+
+    /// 反序列化 front matter。TOML 中的日期时间转为 `%Y-%m-%d %H:%M:%S` 格式的字符串，和 YAML 中的写法一致。
+    /// 出错时返回 [`FrontMatterError`]
+    pub fn parse<T: DeserializeOwned>(&self) -> Result<T, FrontMatterError> {
+        match self.format {
+            FrontMatterFormat::Yaml => serde_yaml::from_str(self.raw).map_err(|err| FrontMatterError {
+                line: err.location().map(|location| location.line() + 1),
+                column: err.location().map(|location| location.column()),
+                message: strip_location(&err.to_string()),
+            }),
+            FrontMatterFormat::Toml => {
+                let toml_error = |err: toml::de::Error| FrontMatterError {
+                    line: err.span().map(|span| self.raw[..span.start].matches('\n').count() + 2),
+                    column: None,
+                    message: err.message().to_string(),
+                };
+                let value: toml::Value = toml::from_str(self.raw).map_err(toml_error)?;
+                stringify_datetimes(value).try_into().map_err(toml_error)
+            },
+        }
+    }
+
+    /// 在 front matter 的第一行插入 `key: "value"` (TOML 为 `key = "value"`)，文件的其余部分保持原样。
+    /// 插在最前面，TOML 中的字段不会落到某个表里。已经有 `key` 字段时 (例如空的 `eid: ""`) 替换那一行，不会写出重复的字段
+    pub fn insert_field(content: &str, key: &str, value: &str) -> Result<String, FrontMatterError> {
+        let front_matter = FrontMatter::split(content)?;
+        let field = match front_matter.format {
+            FrontMatterFormat::Yaml => format!("{key}: \"{value}\""),
+            FrontMatterFormat::Toml => format!("{key} = \"{value}\""),
+        };
+
+        // raw 是 content 的一部分，换算出它在 content 中的位置
+        let mut start = front_matter.raw.as_ptr() as usize - content.as_ptr() as usize;
+        for line in front_matter.raw.split_inclusive('\n') {
+            // TOML 中第一个表之后的字段属于那个表
+            if front_matter.format == FrontMatterFormat::Toml && line.starts_with('[') {
+                break;
+            }
+            if is_field(line, key) {
+                let end = start + line.trim_end_matches(['\r', '\n']).len();
+                return Ok(format!("{}{field}{}", &content[..start], &content[end..]));
+            }
+            start += line.len();
+        }
+
+        // split 保证第一行之后还有结束的分隔符，第一行一定以换行结束
+        let first_end = content.find('\n').map_or(content.len(), |i| i + 1);
+        let newline = match content[..first_end].ends_with("\r\n") {
+            true => "\r\n",
+            false => "\n",
+        };
+        Ok(format!("{}{field}{newline}{}", &content[..first_end], &content[first_end..]))
+    }
+
+    /// `key` 所在的行在文件中的行号，找不到时为 `None`
+    pub fn field_line(&self, key: &str) -> Option<usize> {
+        self.raw.lines().position(|line| is_field(line, key)).map(|i| i + 2)
+    }
+}
+
+/// 这一行是不是顶层的 `key: ...` 或 `key = ...`
+fn is_field(line: &str, key: &str) -> bool {
+    line.strip_prefix(key)
+        .is_some_and(|rest| matches!(rest.trim_start().chars().next(), Some(':' | '=')))
+}
+
+/// 偏移为 `offset` 的时间 `datetime` 在服务器本地时区中的时间
+fn to_local_time(datetime: &str, offset: toml::value::Offset) -> Option<String> {
+    let minutes = match offset {
+        toml::value::Offset::Z => 0,
+        toml::value::Offset::Custom { minutes } => minutes as i32,
+    };
+    let datetime = NaiveDateTime::parse_from_str(datetime, DATE_FORMAT).ok()?;
+    let time = FixedOffset::east_opt(minutes * 60)?.from_local_datetime(&datetime).single()?;
+    Some(time.with_timezone(&Local).format(DATE_FORMAT).to_string())
+}
+
+/// serde_yaml 的错误信息末尾带有 ` at line x column y`，位置单独给出
+fn strip_location(message: &str) -> String {
+    match message.find(" at line ") {
+        Some(i) => message[..i].to_string(),
+        None => message.to_string(),
+    }
+}
+
+/// TOML 的日期时间转为和 YAML 中相同的 `YYYY-MM-DD HH:MM:SS` 文本。
+/// 带时区偏移的时间换算为服务器的本地时间，和 [`crate::now_date`] 一致
+fn stringify_datetimes(value: toml::Value) -> toml::Value {
+    match value {
+        toml::Value::Datetime(datetime) => toml::Value::String(match (datetime.date, datetime.time) {
+            (Some(date), Some(time)) => {
+                let local = format!("{date} {:02}:{:02}:{:02}", time.hour, time.minute, time.second);
+                match datetime.offset {
+                    Some(offset) => to_local_time(&local, offset).unwrap_or_else(|| datetime.to_string()),
+                    None => local,
+                }
+            },
+            _ => datetime.to_string(),
+        }),
+        toml::Value::Array(values) => {
+            toml::Value::Array(values.into_iter().map(stringify_datetimes).collect())
+        },
+        toml::Value::Table(table) => toml::Value::Table(
+            table
+                .into_iter()
+                .map(|(key, value)| (key, stringify_datetimes(value)))
+                .collect(),
+        ),
+        value => value,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn insert_field_yaml() {
+        let content = "---\ntitle: \"a\"\n---\nbody\n";
+        let res = FrontMatter::insert_field(content, "eid", "1").unwrap();
+        assert_eq!(res, "---\neid: \"1\"\ntitle: \"a\"\n---\nbody\n");
+    }
+
+    #[test]
+    fn insert_field_toml() {
+        let content = "+++\ntitle = \"a\"\n[extra]\nkey = 1\n+++\nbody\n";
+        let res = FrontMatter::insert_field(content, "eid", "1").unwrap();
+        assert_eq!(res, "+++\neid = \"1\"\ntitle = \"a\"\n[extra]\nkey = 1\n+++\nbody\n");
+    }
+
+    #[test]
+    fn insert_field_keeps_crlf() {
+        let content = "---\r\ntitle: \"a\"\r\n---\r\nbody\r\n";
+        let res = FrontMatter::insert_field(content, "eid", "1").unwrap();
+        assert_eq!(res, "---\r\neid: \"1\"\r\ntitle: \"a\"\r\n---\r\nbody\r\n");
+    }
+
+    #[test]
+    fn insert_field_replaces_empty_field() {
+        let content = "---\r\ntitle: \"a\"\r\neid: \"\"\r\n---\r\nbody\r\n";
+        let res = FrontMatter::insert_field(content, "eid", "1").unwrap();
+        assert_eq!(res, "---\r\ntitle: \"a\"\r\neid: \"1\"\r\n---\r\nbody\r\n");
+
+        let content = "+++\neid = \"\"\ntitle = \"a\"\n+++\n";
+        let res = FrontMatter::insert_field(content, "eid", "1").unwrap();
+        assert_eq!(res, "+++\neid = \"1\"\ntitle = \"a\"\n+++\n");
+    }
+
+    #[test]
+    fn insert_field_ignores_toml_tables() {
+        let content = "+++\ntitle = \"a\"\n[extra]\neid = \"\"\n+++\n";
+        let res = FrontMatter::insert_field(content, "eid", "1").unwrap();
+        assert_eq!(res, "+++\neid = \"1\"\ntitle = \"a\"\n[extra]\neid = \"\"\n+++\n");
+    }
+
+    #[test]
+    fn insert_field_keeps_bom() {
+        let content = "\u{feff}---\neid: \"\"\n---\n";
+        let res = FrontMatter::insert_field(content, "eid", "1").unwrap();
+        assert_eq!(res, "\u{feff}---\neid: \"1\"\n---\n");
+    }
+
+    #[test]
+    fn insert_field_without_front_matter() {
+        assert!(FrontMatter::insert_field("body\n", "eid", "1").is_err());
+    }
+
+    #[test]
+    fn toml_datetime_offset_is_converted_to_local_time() {
+        let content = "+++\nlocal = 2024-01-01T08:00:00\nshifted = 2024-01-01T08:00:00+08:00\nutc = 2024-01-01T00:00:00Z\n+++\n";
+        let fields: std::collections::HashMap<String, String> = FrontMatter::split(content).unwrap().parse().unwrap();
+        let expected = chrono::DateTime::parse_from_rfc3339("2024-01-01T00:00:00Z")
+            .unwrap()
+            .with_timezone(&Local)
+            .format(DATE_FORMAT)
+            .to_string();
+        assert_eq!(fields["local"], "2024-01-01 08:00:00");
+        assert_eq!(fields["shifted"], expected);
+        assert_eq!(fields["utc"], expected);
+    }
+
+    #[derive(Debug, serde::Deserialize)]
+    struct Fields {
+        title: String,
+        #[serde(default)]
+        count: u32,
+    }
+
+    #[test]
+    fn split_yaml_keeps_body_rules() {
+        let front_matter = FrontMatter::split("---\ntitle: a\n---\nbody\n---\nmore\n").unwrap();
+        assert_eq!(front_matter.format, FrontMatterFormat::Yaml);
+        assert_eq!(front_matter.raw, "title: a\n");
+        assert_eq!(front_matter.body, "body\n---\nmore\n");
+        assert_eq!(front_matter.body_offset, 3);
+    }
+
+    #[test]
+    fn split_toml_with_crlf() {
+        let front_matter = FrontMatter::split("+++\r\ntitle = \"a\"\r\ncount = 2\r\n+++\r\nbody").unwrap();
+        assert_eq!(front_matter.format, FrontMatterFormat::Toml);
+        assert_eq!(front_matter.raw, "title = \"a\"\r\ncount = 2\r\n");
+        assert_eq!(front_matter.body, "body");
+        assert_eq!(front_matter.body_offset, 4);
+        let fields: Fields = front_matter.parse().unwrap();
+        assert_eq!((fields.title.as_str(), fields.count), ("a", 2));
+    }
+
+    #[test]
+    fn split_requires_a_leading_block() {
+        for content in ["", "\n---\ntitle: a\n---\n", "title: a\n---\n", "----\ntitle: a\n----\n"] {
+            let err = FrontMatter::split(content).unwrap_err();
+            assert_eq!(err.line, Some(1), "{content:?}");
+            assert!(err.message.starts_with("missing front matter"), "{content:?}");
+        }
+    }
+
+    #[test]
+    fn split_rejects_unterminated_blocks() {
+        let err = FrontMatter::split("---\ntitle: a\n").unwrap_err();
+        assert_eq!(err.message, "front matter is not closed by `---`");
+        // 分隔符必须和开头的相同
+        let err = FrontMatter::split("+++\ntitle = \"a\"\n---\nbody\n").unwrap_err();
+        assert_eq!(err.message, "front matter is not closed by `+++`");
+    }
+
+    #[test]
+    fn parse_errors_point_into_the_file() {
+        let front_matter = FrontMatter::split("---\ntitle: a\ncount: x\n---\n").unwrap();
+        let err = front_matter.parse::<Fields>().unwrap_err();
+        assert_eq!(err.line, Some(3));
+        assert!(!err.message.contains(" at line "));
+
+        let front_matter = FrontMatter::split("+++\ntitle = \"a\"\ncount = \n+++\n").unwrap();
+        assert_eq!(front_matter.parse::<Fields>().unwrap_err().line, Some(3));
+    }
+
+    #[test]
+    fn field_line_only_matches_top_level_keys() {
+        let front_matter = FrontMatter::split("---\ntitle: a\ndates: []\n  date: nested\ndate: \"x\"\n---\n").unwrap();
+        assert_eq!(front_matter.field_line("date"), Some(5));
+        assert_eq!(front_matter.field_line("title"), Some(2));
+        assert_eq!(front_matter.field_line("brief"), None);
+
+        let front_matter = FrontMatter::split("+++\ntitle = \"a\"\ndate = 2024-01-01\n+++\n").unwrap();
+        assert_eq!(front_matter.field_line("date"), Some(3));
+    }
+
+    #[test]
+    fn toml_dates_become_strings() {
+        let content = "+++\nday = 2024-01-05\nlocal = 2024-01-05T09:08:07.123\n+++\n";
+        let fields: std::collections::HashMap<String, String> = FrontMatter::split(content).unwrap().parse().unwrap();
+        assert_eq!(fields["day"], "2024-01-05");
+        assert_eq!(fields["local"], "2024-01-05 09:08:07");
+    }
+}
@@ -0,0 +1,355 @@
+use std::collections::HashSet;
+
+use anyhow::{anyhow, Result};
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use syntect::{
+    easy::HighlightLines,
+    highlighting::{Theme, ThemeSet},
+    html::{css_for_theme_with_class_style, line_tokens_to_classed_spans, styled_line_to_highlighted_html, ClassStyle, IncludeBackground},
+    parsing::{ParseState, ScopeStack, ScopeStackOp, SyntaxReference, SyntaxSet},
+    util::LinesWithEndings,
+};
+
+lazy_static! {
+    static ref SYNTAX_SET: SyntaxSet = SyntaxSet::load_defaults_newlines();
+    static ref THEME_SET: ThemeSet = ThemeSet::load_defaults();
+}
+
+/// class 模式下所有 class 的前缀，避免和前端的样式冲突
+const CLASS_STYLE: ClassStyle = ClassStyle::SpacedPrefixed { prefix: "hl-" };
+
+/// 代码高亮的输出方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HighlightMode {
+    /// 输出带 class 的 span，样式由 `css_path` 中的 css 提供
+    #[default]
+    Class,
+    /// 直接输出带 style 的 span
+    Inline,
+}
+
+/// config.json 中的代码高亮配置
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct HighlightConfig {
+    pub enabled: bool,
+    pub mode: HighlightMode,
+    /// syntect 自带的主题名，例如 `InspiredGitHub`, `base16-ocean.dark`
+    pub theme: String,
+    /// class 模式下把主题 css 写到这个路径
+    pub css_path: Option<String>,
+}
+
+impl Default for HighlightConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            mode: HighlightMode::Class,
+            theme: String::from("InspiredGitHub"),
+            css_path: None,
+        }
+    }
+}
+
+/// 代码块 info string 中的属性，例如
+/// ```` ```rust {linenos hl_lines=1,3-4 title="main.rs"} ````
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FenceInfo {
+    pub lang: Option<String>,
+    pub linenos: bool,
+    pub hl_lines: HashSet<usize>,
+    pub title: Option<String>,
+}
+
+impl FenceInfo {
+    pub fn parse(info: &str) -> Self {
+        let mut res = Self::default();
+        let info = info.trim();
+        let (lang, attrs) = match info.find(|c: char| c.is_whitespace() || c == '{') {
+            Some(i) => (&info[..i], &info[i..]),
+            None => (info, ""),
+        };
+        if !lang.is_empty() {
+            res.lang = Some(lang.to_string());
+        }
+        let attrs = attrs.trim().trim_start_matches('{').trim_end_matches('}');
+        for attr in split_attrs(attrs) {
+            let (key, value) = match attr.split_once('=') {
+                Some((key, value)) => (key, Some(value.trim_matches('"').trim_matches('\''))),
+                None => (attr.as_str(), None),
+            };
+            match (key, value) {
+                ("linenos", _) => res.linenos = true,
+                ("hl_lines", Some(value)) => res.hl_lines = parse_line_ranges(value),
+                ("title" | "filename", Some(value)) => res.title = Some(value.to_string()),
+                _ => {},
+            }
+        }
+        res
+    }
+}
+
+/// 按空白切分属性，引号内的空白保留
+fn split_attrs(attrs: &str) -> Vec<String> {
+    let mut res = Vec::new();
+    let mut cur = String::new();
+    let mut quote = None;
+    for c in attrs.chars() {
+        match (quote, c) {
+            (None, '"' | '\'') => {
+                quote = Some(c);
+                cur.push(c);
+            },
+            (Some(q), _) if q == c => {
+                quote = None;
+                cur.push(c);
+            },
+            (None, _) if c.is_whitespace() => {
+                if !cur.is_empty() {
+                    res.push(std::mem::take(&mut cur));
+                }
+            },
+            _ => cur.push(c),
+        }
+    }
+    if !cur.is_empty() {
+        res.push(cur);
+    }
+    res
+}
+
+/// `1,3-4` 或 `1 3-4` → {1, 3, 4}
+fn parse_line_ranges(value: &str) -> HashSet<usize> {
+    let mut res = HashSet::new();
+    for range in value.split(|c: char| c == ',' || c.is_whitespace()).filter(|s| !s.is_empty()) {
+        let (start, end) = range.split_once('-').unwrap_or((range, range));
+        if let (Ok(start), Ok(end)) = (start.trim().parse::<usize>(), end.trim().parse::<usize>()) {
+            res.extend(start..=end);
+        }
+    }
+    res
+}
+
+/// 代码高亮器
+pub struct CodeHighlighter {
+    mode: HighlightMode,
+    theme: &'static Theme,
+}
+
+impl CodeHighlighter {
+    pub fn new(config: &HighlightConfig) -> Result<Self> {
+        let theme = THEME_SET
+            .themes
+            .get(&config.theme)
+            .ok_or_else(|| anyhow!("unknown highlight theme `{}`", config.theme))?;
+        Ok(Self { mode: config.mode, theme })
+    }
+
+    /// class 模式所需的 css
+    pub fn css(&self) -> Result<String> {
+        Ok(css_for_theme_with_class_style(self.theme, CLASS_STYLE)?)
+    }
+
+    /// 把代码块渲染为 html
+    pub fn highlight(&self, info: &FenceInfo, code: &str) -> Result<String> {
+        let syntax = info
+            .lang
+            .as_deref()
+            .and_then(|lang| SYNTAX_SET.find_syntax_by_token(lang))
+            .unwrap_or_else(|| SYNTAX_SET.find_syntax_plain_text());
+        let lines = match self.mode {
+            HighlightMode::Class => self.class_lines(syntax, code)?,
+            HighlightMode::Inline => self.inline_lines(syntax, code)?,
+        };
+
+        let mut html = String::new();
+        if let Some(title) = &info.title {
+            html.push_str(r#"<figure class="code-block"><figcaption>"#);
+            html.push_str(&escape_html(title));
+            html.push_str("</figcaption>");
+        }
+        html.push_str(r#"<pre class="code""#);
+        if let Some(lang) = &info.lang {
+            html.push_str(&format!(r#" data-lang="{}""#, escape_html(lang)));
+        }
+        if self.mode == HighlightMode::Inline {
+            if let Some(bg) = self.theme.settings.background {
+                html.push_str(&format!(
+                    r#" style="background-color:#{:02x}{:02x}{:02x};""#,
+                    bg.r, bg.g, bg.b
+                ));
+            }
+        }
+        html.push_str("><code>");
+        for (i, line) in lines.iter().enumerate() {
+            let n = i + 1;
+            if info.hl_lines.contains(&n) {
+                html.push_str(r#"<span class="line hl">"#);
+            } else {
+                html.push_str(r#"<span class="line">"#);
+            }
+            if info.linenos {
+                html.push_str(&format!(r#"<span class="ln">{n}</span>"#));
+            }
+            html.push_str(line);
+            html.push_str("</span>\n");
+        }
+        html.push_str("</code></pre>");
+        if info.title.is_some() {
+            html.push_str("</figure>");
+        }
+        html.push('\n');
+        Ok(html)
+    }
+
+    /// 逐行输出带 class 的 span，跨行的 scope 在行尾关闭、下一行开头重新打开
+    fn class_lines(&self, syntax: &SyntaxReference, code: &str) -> Result<Vec<String>> {
+        let mut parse_state = ParseState::new(syntax);
+        let mut stack = ScopeStack::new();
+        let mut res = Vec::new();
+        for line in LinesWithEndings::from(code) {
+            let mut html = String::new();
+            for scope in stack.as_slice() {
+                let classes: Vec<String> = scope
+                    .build_string()
+                    .split('.')
+                    .map(|atom| format!("hl-{atom}"))
+                    .collect();
+                html.push_str(&format!(r#"<span class="{}">"#, classes.join(" ")));
+            }
+            let ops = parse_state.parse_line(line, &SYNTAX_SET)?;
+            let (spans, _) = line_tokens_to_classed_spans(
+                line.trim_end_matches('\n'),
+                &trim_ops(&ops, line),
+                CLASS_STYLE,
+                &mut stack,
+            )?;
+            html.push_str(&spans);
+            html.push_str(&"</span>".repeat(stack.len()));
+            res.push(html);
+        }
+        Ok(res)
+    }
+
+    fn inline_lines(&self, syntax: &SyntaxReference, code: &str) -> Result<Vec<String>> {
+        let mut highlighter = HighlightLines::new(syntax, self.theme);
+        let mut res = Vec::new();
+        for line in LinesWithEndings::from(code) {
+            let regions: Vec<_> = highlighter
+                .highlight_line(line, &SYNTAX_SET)?
+                .into_iter()
+                .map(|(style, text)| (style, text.trim_end_matches('\n')))
+                .collect();
+            res.push(styled_line_to_highlighted_html(&regions, IncludeBackground::No)?);
+        }
+        Ok(res)
+    }
+}
+
+/// 去掉行尾换行符后，落在换行符之后的 op 移到行尾
+fn trim_ops(ops: &[(usize, ScopeStackOp)], line: &str) -> Vec<(usize, ScopeStackOp)> {
+    let len = line.trim_end_matches('\n').len();
+    ops.iter()
+        .map(|(i, op)| ((*i).min(len), op.clone()))
+        .collect()
+}
+
+pub(crate) fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(lines: &[usize]) -> HashSet<usize> {
+        lines.iter().copied().collect()
+    }
+
+    #[test]
+    fn fence_info_attributes() {
+        let info = FenceInfo::parse(r#"rust {linenos hl_lines=1,3-4 title="src/main.rs"}"#);
+        assert_eq!(info.lang.as_deref(), Some("rust"));
+        assert!(info.linenos);
+        assert_eq!(info.hl_lines, lines(&[1, 3, 4]));
+        assert_eq!(info.title.as_deref(), Some("src/main.rs"));
+
+        let info = FenceInfo::parse(r#"py{filename='my file.py' hl_lines="2 5-6" unknown}"#);
+        assert_eq!(info.lang.as_deref(), Some("py"));
+        assert!(!info.linenos);
+        assert_eq!(info.hl_lines, lines(&[2, 5, 6]));
+        assert_eq!(info.title.as_deref(), Some("my file.py"));
+    }
+
+    #[test]
+    fn fence_info_without_attributes() {
+        assert_eq!(FenceInfo::parse(""), FenceInfo::default());
+        assert_eq!(FenceInfo::parse(" toml ").lang.as_deref(), Some("toml"));
+        assert_eq!(FenceInfo::parse("{linenos}").lang, None);
+    }
+
+    #[test]
+    fn line_ranges_skip_invalid_parts() {
+        assert_eq!(parse_line_ranges("1, 3-4,,7"), lines(&[1, 3, 4, 7]));
+        assert_eq!(parse_line_ranges("x,2-y,5-3,6"), lines(&[6]));
+        assert!(parse_line_ranges("").is_empty());
+    }
+
+    fn highlighter(mode: HighlightMode) -> CodeHighlighter {
+        CodeHighlighter::new(&HighlightConfig { mode, ..Default::default() }).unwrap()
+    }
+
+    #[test]
+    fn highlighted_lines_and_line_numbers() {
+        let info = FenceInfo::parse("rust {linenos hl_lines=2}");
+        let html = highlighter(HighlightMode::Class).highlight(&info, "fn main() {}\nlet x = 1;\n").unwrap();
+        assert!(html.starts_with(r#"<pre class="code" data-lang="rust"><code><span class="line"><span class="ln">1</span>"#), "{html}");
+        assert!(html.contains("\n<span class=\"line hl\"><span class=\"ln\">2</span>"), "{html}");
+        assert!(html.ends_with("</span>\n</code></pre>\n"), "{html}");
+        assert_eq!(html.matches(r#"<span class="line"#).count(), 2);
+
+        let html = highlighter(HighlightMode::Class).highlight(&FenceInfo::parse("rust"), "a\nb\n").unwrap();
+        assert!(!html.contains(r#"class="ln""#) && !html.contains("line hl"), "{html}");
+    }
+
+    #[test]
+    fn title_becomes_a_caption() {
+        let info = FenceInfo::parse(r#"rust {filename="a<b>.rs"}"#);
+        let html = highlighter(HighlightMode::Class).highlight(&info, "fn main() {}\n").unwrap();
+        assert!(html.starts_with(r#"<figure class="code-block"><figcaption>a&lt;b&gt;.rs</figcaption><pre class="code""#), "{html}");
+        assert!(html.ends_with("</code></pre></figure>\n"), "{html}");
+    }
+
+    #[test]
+    fn class_and_inline_modes() {
+        let info = FenceInfo::parse("rust");
+        let html = highlighter(HighlightMode::Class).highlight(&info, "fn main() {}\n").unwrap();
+        assert!(html.contains(r#"<span class="hl-storage hl-type hl-function hl-rust">fn</span>"#), "{html}");
+        assert!(!html.contains("style="), "{html}");
+
+        let html = highlighter(HighlightMode::Inline).highlight(&info, "fn main() {}\n").unwrap();
+        assert!(html.starts_with(r#"<pre class="code" data-lang="rust" style="background-color:#ffffff;"><code>"#), "{html}");
+        assert!(html.contains(r#"<span style="font-weight:bold;color:#a71d5d;">fn </span>"#), "{html}");
+        assert!(!html.contains("hl-"), "{html}");
+    }
+
+    #[test]
+    fn unknown_languages_are_plain_text() {
+        let html = highlighter(HighlightMode::Class).highlight(&FenceInfo::parse("nosuchlang"), "<a> & b\n").unwrap();
+        assert_eq!(
+            html,
+            "<pre class=\"code\" data-lang=\"nosuchlang\"><code><span class=\"line\"><span class=\"hl-text hl-plain\">&lt;a&gt; &amp; b</span></span>\n</code></pre>\n"
+        );
+    }
+
+    #[test]
+    fn unknown_themes_are_rejected() {
+        let config = HighlightConfig { theme: String::from("nope"), ..Default::default() };
+        assert!(CodeHighlighter::new(&config).is_err());
+    }
+}
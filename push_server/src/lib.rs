@@ -1,12 +1,46 @@
+pub mod check;
 pub mod data_struct;
 pub mod dbops;
+pub mod front_matter;
+pub mod highlight;
+pub mod markdown;
+pub mod math;
+pub mod search;
+pub mod sync;
+pub mod time;
 
+use anyhow::Result;
 use lazy_static::lazy_static;
-use std::{env, time::{SystemTime, UNIX_EPOCH}};
+use std::{env, fs, time::{SystemTime, UNIX_EPOCH}};
 
 lazy_static! {
     pub static ref DATABASE_URL: String = env::var("DATABASE_URL").expect("DATABASE_URL is not defined");
-    pub static ref CURRENT_TIME: f64 = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs_f64();
+}
+
+/// 递归得到 `dir` 文件夹下所有后缀为 `suffix` 的文件路径。
+/// 目录不能读取时返回错误，不会把配置错误的文章目录当成空目录
+pub fn get_entries(dir: &str, suffix: &str) -> Result<Vec<String>> {
+    let mut res = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            res.extend(get_entries(&path.to_string_lossy(), suffix)?);
+        } else if path.extension().is_some_and(|ext| ext == suffix) {
+            res.push(path.to_string_lossy().to_string());
+        }
+    }
+    Ok(res)
+}
+
+/// 当前的 unix 时间戳 (秒)，每次写入数据库时取一次，作为文章的 last_save_time
+pub fn now() -> f64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs_f64()
+}
+
+/// 当前的本地时间，格式和 front matter 中的 `date` 相同。
+/// 查询时和 `publish_at` 比较，定时发布的文章到时间后自动出现，不需要再次同步
+pub fn now_date() -> String {
+    chrono::Local::now().format(time::DATE_FORMAT).to_string()
 }
 
 #[cfg(test)]
@@ -1,19 +1,26 @@
-use std::collections::HashMap;
+use std::sync::Arc;
+
 use push_server::{
-    data_struct::Essay, dbops::{tables_ops::*, utils::*},
-    CURRENT_TIME,
+    check::check_essays,
+    data_struct::{MarkdownRenderer, RendererConfig}, dbops::store::{self, EssayStore},
+    get_entries, now,
+    sync::{load_essays, SyncChange, SyncReport},
 };
 use tokio::fs;
 use serde::{Deserialize, Serialize};
 use anyhow::Result;
 use dotenv::dotenv;
+mod cli;
 mod utils;
-use utils::*;
+mod watch;
+use cli::{Command, SyncOptions, USAGE};
 
 /// 读取 config.json 文件
 #[derive(Clone, Deserialize, Serialize)]
 struct Config {
     essays_source: String,
+    #[serde(default)]
+    renderer: RendererConfig,
 }
 
 impl Config {
@@ -25,7 +32,8 @@ impl Config {
             },
             Err(_) => {
                 let res = Self {
-                    essays_source: String::from("./res/_essays")
+                    essays_source: String::from("./res/_essays"),
+                    renderer: RendererConfig::default(),
                 };
                 fs::write(config_path, serde_json::to_string_pretty(&res).unwrap()).await.unwrap();
                 res
@@ -43,37 +51,135 @@ async fn main() -> Result<()> {
     
     initialize().await;
 
+    let command = Command::from_args()?;
     let config = Config::new().await;
-    let essays_source = config.essays_source;
-    let pool = build_pool().await?;
+    let renderer = MarkdownRenderer::with_config(&config.renderer)?;
+    match command {
+        Command::Sync(options) => sync(&config, &renderer, &options).await,
+        Command::Check => check(&config, &renderer).await,
+        Command::Watch => watch::watch(&config, &renderer).await,
+        Command::Migrate => {
+            connect(|label, detail| println!("->> {:<12} - {detail}", label)).await?;
+            println!("database schema is up to date");
+            Ok(())
+        },
+        Command::Help => {
+            print!("{USAGE}");
+            Ok(())
+        },
+    }
+}
 
-    let db_essay_last_save_time = query_essays_last_save_time(&pool).await?;
-    let mut file_essay_last_save_time = HashMap::new();
-    let essays_path = utils::get_entries(&essays_source, "md");
-    let mut essays = Vec::new();
-    for essay_path in essays_path {
-        essays.push(Essay::crate_from_path(&essay_path).await.expect(&(essay_path.clone() + " yaml is error")));
-        file_essay_last_save_time.insert(essays.last().unwrap().eid.clone(), get_modified_time(&essay_path)?);
+/// 检查所有文章并打印报告，有问题时以 1 退出
+async fn check(config: &Config, renderer: &MarkdownRenderer) -> Result<()> {
+    let mut essays_path = get_entries(&config.essays_source, "md")?;
+    essays_path.sort();
+    let diagnostics = check_essays(&essays_path, renderer).await;
+    for diagnostic in &diagnostics {
+        println!("{diagnostic}");
     }
-    
-    for (eid, _) in db_essay_last_save_time.iter() {
-        if !file_essay_last_save_time.contains_key(eid) {
-            delete_essay(&pool, eid).await?;
-            println!("->> {:<12} - {}", "DELETE", eid);
-        }
+    if diagnostics.is_empty() {
+        println!("checked {} essays, no problems found", essays_path.len());
+        return Ok(());
+    }
+    let mut bad_files: Vec<_> = diagnostics.iter().map(|diagnostic| &diagnostic.path).collect();
+    bad_files.dedup();
+    println!(
+        "\n{} problems found in {} of {} essays",
+        diagnostics.len(),
+        bad_files.len(),
+        essays_path.len()
+    );
+    std::process::exit(1);
+}
+
+/// 把新增、修改的文章写入数据库，删除已经不存在的文章。
+/// 所有写入在一个事务中完成，有文章读取或写入失败时不写入任何内容并以 1 退出。
+/// `--dry-run` 时只打印计划
+async fn sync(config: &Config, renderer: &MarkdownRenderer, options: &SyncOptions) -> Result<()> {
+    // json 报告输出到标准输出时，日志改为输出到标准错误
+    let report_to_stdout = options.report.as_deref() == Some("-");
+    let log = |label: &str, detail: &str| match report_to_stdout {
+        true => eprintln!("->> {:<12} - {detail}", label),
+        false => println!("->> {:<12} - {detail}", label),
+    };
+
+    if !options.dry_run {
+        renderer.write_highlight_css(&config.renderer).await?;
+    }
+    // dry run 不改变数据库，也不执行迁移
+    let store = match options.dry_run {
+        true => store::connect().await?,
+        false => connect(log).await?,
+    };
+    // 整个同步期间持有同步锁，拒绝同时运行的另一个 push_server
+    let session = match options.dry_run {
+        true => None,
+        false => Some(store.begin_sync().await?),
+    };
+
+    let db_state = store.query_essays_sync_state().await?;
+    let mut batch = load_essays(get_entries(&config.essays_source, "md")?, renderer).await;
+    for warning in &batch.warnings {
+        log("WARN", warning);
+    }
+    for (path, eid) in utils::assign_eids(&mut batch, options.dry_run) {
+        log(if options.dry_run { "PLAN EID" } else { "EID" }, &format!("{path} -> {eid}"));
+    }
+    for error in &batch.errors {
+        log("ERROR", &error.message);
     }
 
-    for essay in &essays {
-        if db_essay_last_save_time.contains_key(&essay.eid) {
-            if file_essay_last_save_time.get(&essay.eid).unwrap() > db_essay_last_save_time.get(&essay.eid).unwrap() {
-                update_essay(&pool, essay, *CURRENT_TIME).await?;
-                println!("->> {:<12} - {}", "UPDATE", essay.title);
+    // 有文章读取失败时无法确定哪些文章真正被删除了，计划中不包含删除
+    let plan = push_server::sync::plan(batch.essays, &db_state, batch.errors.is_empty(), options.force);
+    let mut report = SyncReport::new(options.dry_run, &plan);
+    report.warnings = batch.warnings;
+    report.errors = batch.errors;
+    for change in &plan.changes {
+        let label = match options.dry_run {
+            true => format!("PLAN {}", change.action().label()),
+            false => change.action().label().to_string(),
+        };
+        log(&label, &change_summary(change));
+    }
+    if let Some(mut session) = session {
+        if report.errors.is_empty() {
+            if let Err(err) = session.apply(&plan, now()).await {
+                log("ERROR", &err.message);
+                report.errors.push(err);
             }
         } else {
-            insert_essay(&pool, essay, *CURRENT_TIME).await?;
-            println!("->> {:<12} - {}", "INSERT", essay.title);
+            log("ERROR", "some essays could not be read, nothing was written");
         }
+        session.release().await?;
     }
 
+    if let Some(path) = &options.report {
+        let json = serde_json::to_string_pretty(&report)?;
+        match report_to_stdout {
+            true => println!("{json}"),
+            false => fs::write(path, json).await?,
+        }
+    }
+    if !report.errors.is_empty() {
+        std::process::exit(1);
+    }
     Ok(())
-}
\ No newline at end of file
+}
+
+/// 连接数据库并执行还没有执行过的迁移，`log` 打印执行的每个迁移
+async fn connect(log: impl Fn(&str, &str)) -> Result<Arc<dyn EssayStore>> {
+    let store = store::connect().await?;
+    for (version, description) in store.migrate().await? {
+        log("MIGRATE", &format!("{version} {description}"));
+    }
+    Ok(store)
+}
+
+/// 日志中显示的变更对象，删除的文章显示 eid，其余显示标题
+fn change_summary(change: &SyncChange) -> String {
+    match change {
+        SyncChange::Delete { eid, .. } => eid.clone(),
+        _ => change.title().to_string(),
+    }
+}
@@ -0,0 +1,535 @@
+use std::collections::{HashMap, HashSet};
+
+use anyhow::Result;
+use pulldown_cmark::{html, CodeBlockKind, CowStr, Event, HeadingLevel, Options, Parser, Tag, TagEnd};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    highlight::{escape_html, CodeHighlighter, FenceInfo, HighlightConfig},
+    math::{MathConfig, MathRenderer},
+};
+
+/// config.json 中的渲染配置
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct RendererConfig {
+    pub extensions: ExtensionConfig,
+    pub highlight: HighlightConfig,
+    pub math: MathConfig,
+}
+
+impl RendererConfig {
+    /// 读取 push_server 的 config.json 中的 `renderer` 一项，没有这一项时使用默认配置。
+    /// 不经过数据库直接加载文章时，用它得到和 push_server 相同的渲染结果
+    pub fn from_config_file(path: &str) -> Result<Self> {
+        #[derive(Deserialize)]
+        struct ConfigFile {
+            #[serde(default)]
+            renderer: RendererConfig,
+        }
+        let content = std::fs::read_to_string(path)?;
+        let config: ConfigFile = serde_json::from_str(&content)?;
+        Ok(config.renderer)
+    }
+}
+
+/// 各个 GFM 扩展的开关，默认全部开启
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ExtensionConfig {
+    pub tables: bool,
+    pub footnotes: bool,
+    pub strikethrough: bool,
+    pub tasklists: bool,
+    /// 把引号、`--`、`...` 转为对应的排版符号
+    pub smart_punctuation: bool,
+    /// `# 标题 {#id .class}`
+    pub heading_attributes: bool,
+}
+
+impl Default for ExtensionConfig {
+    fn default() -> Self {
+        Self {
+            tables: true,
+            footnotes: true,
+            strikethrough: true,
+            tasklists: true,
+            smart_punctuation: true,
+            heading_attributes: true,
+        }
+    }
+}
+
+impl ExtensionConfig {
+    fn options(&self) -> Options {
+        let mut option = Options::empty();
+        option.set(Options::ENABLE_TABLES, self.tables);
+        option.set(Options::ENABLE_FOOTNOTES, self.footnotes);
+        option.set(Options::ENABLE_STRIKETHROUGH, self.strikethrough);
+        option.set(Options::ENABLE_TASKLISTS, self.tasklists);
+        option.set(Options::ENABLE_SMART_PUNCTUATION, self.smart_punctuation);
+        option.set(Options::ENABLE_HEADING_ATTRIBUTES, self.heading_attributes);
+        option
+    }
+}
+
+/// 目录中的一项，`children` 为下一级标题
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct TocEntry {
+    pub level: u8,
+    pub id: String,
+    pub title: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub children: Vec<TocEntry>,
+}
+
+/// 渲染过程中发现的问题，`line` 从 1 开始，相对于传入的 markdown 文本
+#[derive(Debug, Clone, PartialEq)]
+pub struct RenderWarning {
+    pub line: usize,
+    pub message: String,
+}
+
+/// 渲染结果
+#[derive(Debug, Clone, Default)]
+pub struct Rendered {
+    pub html: String,
+    pub toc: Vec<TocEntry>,
+    pub warnings: Vec<RenderWarning>,
+}
+
+/// markdown 渲染器
+pub struct MarkdownRenderer {
+    option: Options,
+    highlighter: Option<CodeHighlighter>,
+    math: Option<MathRenderer>,
+}
+
+impl MarkdownRenderer {
+    pub fn new() -> Self {
+        Self::with_config(&RendererConfig::default()).expect("default renderer config is valid")
+    }
+
+    pub fn with_config(config: &RendererConfig) -> Result<Self> {
+        let mut option = config.extensions.options();
+        let highlighter = match config.highlight.enabled {
+            true => Some(CodeHighlighter::new(&config.highlight)?),
+            false => None,
+        };
+        let math = match config.math.enabled {
+            true => {
+                option.insert(Options::ENABLE_MATH);
+                Some(MathRenderer::new()?)
+            },
+            false => None,
+        };
+        Ok(Self { option, highlighter, math })
+    }
+
+    /// class 模式下代码高亮所需的 css，未开启高亮时为 `None`
+    pub fn highlight_css(&self) -> Result<Option<String>> {
+        self.highlighter
+            .as_ref()
+            .map(CodeHighlighter::css)
+            .transpose()
+    }
+
+    /// class 模式的高亮 css 写到 `config` 中的 `css_path`，没有设置 `css_path` 或未开启高亮时什么都不做
+    pub async fn write_highlight_css(&self, config: &RendererConfig) -> Result<()> {
+        if let (Some(css_path), Some(css)) = (&config.highlight.css_path, self.highlight_css()?) {
+            tokio::fs::write(css_path, css).await?;
+        }
+        Ok(())
+    }
+
+    /// 渲染为 html，同时给每个标题加上唯一的 id 并生成目录，代码块在这里完成高亮，公式在这里转为 MathML，
+    /// 脚注统一放到文末带回链的脚注区。公式有误、图片缺少 alt 文本时记录 warning
+    pub async fn render(&self, md_content: &str) -> Result<Rendered> {
+        let parser = Parser::new_ext(md_content, self.option).into_offset_iter();
+        let mut warnings = Vec::new();
+        let mut slugs = HashSet::new();
+        let mut headings = Vec::new();
+        let mut events = Vec::new();
+        // 正在处理的标题: (标题的 Start 事件, 标题内的事件, 标题文本)。
+        // 公式在标题文本中保留 TeX 源码，目录里不会变成空白
+        let mut heading: Option<(Tag, Vec<Event>, String)> = None;
+        // 正在处理的代码块: (info string, 代码)
+        let mut code_block: Option<(FenceInfo, String)> = None;
+        // 正在处理的脚注定义: (label, 脚注内的事件)
+        let mut footnote: Option<(CowStr, Vec<Event>)> = None;
+        let mut footnotes = Footnotes::default();
+        // 正在处理的图片: (图片所在的行, 地址, alt 文本)
+        let mut image: Option<(usize, String, String)> = None;
+        let lines = LineIndex::new(md_content);
+
+        for (event, range) in parser {
+            match &event {
+                Event::Start(Tag::Image { dest_url, .. }) => {
+                    image = Some((lines.line(range.start), dest_url.to_string(), String::new()));
+                },
+                Event::Text(text) | Event::Code(text) => {
+                    if let Some((_, _, alt)) = image.as_mut() {
+                        alt.push_str(text);
+                    }
+                    if let Some((_, _, title)) = heading.as_mut() {
+                        title.push_str(text);
+                    }
+                },
+                Event::InlineMath(tex) | Event::DisplayMath(tex) => {
+                    if let Some((_, _, title)) = heading.as_mut() {
+                        title.push_str(tex);
+                    }
+                },
+                Event::End(TagEnd::Image) => {
+                    if let Some((line, url, alt)) = image.take() {
+                        if alt.trim().is_empty() {
+                            warnings.push(RenderWarning {
+                                line,
+                                message: format!("image `{url}` has no alt text"),
+                            });
+                        }
+                    }
+                },
+                _ => {},
+            }
+            let event = match event {
+                Event::InlineMath(tex) => self.math_event(&tex, false, lines.line(range.start), &mut warnings),
+                Event::DisplayMath(tex) => self.math_event(&tex, true, lines.line(range.start), &mut warnings),
+                event => event,
+            };
+            match event {
+                Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(info))) if self.highlighter.is_some() => {
+                    code_block = Some((FenceInfo::parse(&info), String::new()));
+                },
+                Event::Text(text) if code_block.is_some() => {
+                    if let Some((_, code)) = code_block.as_mut() {
+                        code.push_str(&text);
+                    }
+                },
+                Event::End(TagEnd::CodeBlock) if code_block.is_some() => {
+                    if let (Some((info, code)), Some(highlighter)) = (code_block.take(), &self.highlighter) {
+                        let html = highlighter.highlight(&info, &code)?;
+                        target(&mut footnote, &mut events).push(Event::Html(CowStr::from(html)));
+                    }
+                },
+                Event::Start(tag @ Tag::Heading { .. }) => {
+                    heading = Some((tag, Vec::new(), String::new()));
+                },
+                Event::End(TagEnd::Heading(_)) => {
+                    let Some((Tag::Heading { level, id, classes, attrs }, inner, title)) = heading.take() else {
+                        continue;
+                    };
+                    let title = title.trim().to_string();
+                    let id = unique_slug(&mut slugs, id.as_deref().unwrap_or(&slugify(&title)));
+                    headings.push(TocEntry {
+                        level: heading_level(level),
+                        id: id.clone(),
+                        title,
+                        children: Vec::new(),
+                    });
+                    let events = target(&mut footnote, &mut events);
+                    events.push(Event::Start(Tag::Heading { level, id: Some(CowStr::from(id)), classes, attrs }));
+                    events.extend(inner);
+                    events.push(Event::End(TagEnd::Heading(level)));
+                },
+                Event::Start(Tag::FootnoteDefinition(label)) => {
+                    footnote = Some((label, Vec::new()));
+                },
+                Event::End(TagEnd::FootnoteDefinition) => {
+                    if let Some((label, inner)) = footnote.take() {
+                        footnotes.define(label.into_string(), inner);
+                    }
+                },
+                Event::FootnoteReference(label) => {
+                    let html = footnotes.reference(&label);
+                    let event = Event::InlineHtml(CowStr::from(html));
+                    match heading.as_mut() {
+                        Some((_, inner, _)) => inner.push(event),
+                        None => target(&mut footnote, &mut events).push(event),
+                    }
+                },
+                event => match heading.as_mut() {
+                    Some((_, inner, _)) => inner.push(event),
+                    None => target(&mut footnote, &mut events).push(event),
+                },
+            }
+        }
+
+        let mut html_output = String::new();
+        html::push_html(&mut html_output, events.into_iter());
+        html_output.push_str(&footnotes.render());
+        Ok(Rendered {
+            html: html_output,
+            toc: build_toc(headings),
+            warnings,
+        })
+    }
+
+    /// 公式转为 MathML，TeX 有误时原样输出源码并记录一条 warning
+    fn math_event(&self, tex: &str, display: bool, line: usize, warnings: &mut Vec<RenderWarning>) -> Event<'static> {
+        let Some(math) = &self.math else {
+            return Event::Text(CowStr::from(tex.to_string()));
+        };
+        match math.render(tex, display) {
+            Ok(mathml) => Event::InlineHtml(CowStr::from(mathml)),
+            Err(err) => {
+                warnings.push(RenderWarning {
+                    line,
+                    message: format!("invalid TeX `{}`: {err}", tex.trim().replace('\n', " ")),
+                });
+                let delimiter = if display { "$$" } else { "$" };
+                Event::InlineHtml(CowStr::from(format!(
+                    r#"<code class="math-error">{}</code>"#,
+                    escape_html(&format!("{delimiter}{tex}{delimiter}"))
+                )))
+            },
+        }
+    }
+}
+
+impl Default for MarkdownRenderer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// 由标题文本生成 slug：保留字母、数字 (包括中日韩文字) 和 `_`，
+/// 空白和 `-` 合并为一个 `-`，其余标点丢弃
+pub fn slugify(text: &str) -> String {
+    let mut slug = String::new();
+    let mut dash = false;
+    for c in text.trim().chars() {
+        if c.is_alphanumeric() || c == '_' {
+            if dash && !slug.is_empty() {
+                slug.push('-');
+            }
+            dash = false;
+            slug.extend(c.to_lowercase());
+        } else if c.is_whitespace() || c == '-' {
+            dash = true;
+        }
+    }
+    if slug.is_empty() {
+        slug.push_str("section");
+    }
+    slug
+}
+
+/// 重复的 slug 依次加上 `-1`, `-2` ...
+fn unique_slug(slugs: &mut HashSet<String>, slug: &str) -> String {
+    let mut res = slug.to_string();
+    let mut n = 1;
+    while slugs.contains(&res) {
+        res = format!("{slug}-{n}");
+        n += 1;
+    }
+    slugs.insert(res.clone());
+    res
+}
+
+/// 脚注定义内的事件收集到脚注里，其余的直接输出
+fn target<'a, 'b>(
+    footnote: &'b mut Option<(CowStr<'a>, Vec<Event<'a>>)>,
+    events: &'b mut Vec<Event<'a>>,
+) -> &'b mut Vec<Event<'a>> {
+    match footnote {
+        Some((_, inner)) => inner,
+        None => events,
+    }
+}
+
+/// 按第一次引用的顺序给脚注编号，引用处为 `fnref-{n}` (同一脚注的后续引用为 `fnref-{n}-{k}`)，
+/// 脚注为 `fn-{n}`，每个脚注末尾带回到所有引用处的链接
+#[derive(Default)]
+struct Footnotes<'a> {
+    /// label → 脚注内容
+    definitions: HashMap<String, Vec<Event<'a>>>,
+    /// 按编号排列的 (label, 被引用的次数)
+    references: Vec<(String, usize)>,
+}
+
+impl<'a> Footnotes<'a> {
+    fn define(&mut self, label: String, events: Vec<Event<'a>>) {
+        self.definitions.entry(label).or_insert(events);
+    }
+
+    /// 记录一次引用，返回引用处的 html
+    fn reference(&mut self, label: &str) -> String {
+        let n = match self.references.iter().position(|(l, ..)| l == label) {
+            Some(i) => i,
+            None => {
+                self.references.push((label.to_string(), 0));
+                self.references.len() - 1
+            },
+        };
+        let count = &mut self.references[n].1;
+        *count += 1;
+        let n = n + 1;
+        let id = ref_id(n, *count);
+        format!(r##"<sup class="footnote-ref" id="{id}"><a href="#fn-{n}">{n}</a></sup>"##)
+    }
+
+    /// 文末的脚注区，没有引用任何脚注时为空
+    fn render(self) -> String {
+        let Self { mut definitions, references } = self;
+        if references.is_empty() {
+            return String::new();
+        }
+        let mut html = String::from("<section class=\"footnotes\">\n<ol>\n");
+        for (i, (label, count)) in references.into_iter().enumerate() {
+            let n = i + 1;
+            let mut content = String::new();
+            html::push_html(&mut content, definitions.remove(&label).unwrap_or_default().into_iter());
+            let backrefs: String = (1..=count)
+                .map(|k| format!(r##" <a href="#{}" class="footnote-backref">↩</a>"##, ref_id(n, k)))
+                .collect();
+            // 回链放进最后一段里，避免单独占一行
+            let content = content.trim_end();
+            html.push_str(&format!("<li id=\"fn-{n}\">\n"));
+            match content.strip_suffix("</p>") {
+                Some(content) => html.push_str(&format!("{content}{backrefs}</p>\n")),
+                None => html.push_str(&format!("{content}\n<p>{}</p>\n", backrefs.trim_start())),
+            }
+            html.push_str("</li>\n");
+        }
+        html.push_str("</ol>\n</section>\n");
+        html
+    }
+}
+
+fn ref_id(n: usize, k: usize) -> String {
+    match k {
+        1 => format!("fnref-{n}"),
+        k => format!("fnref-{n}-{k}"),
+    }
+}
+
+/// 每一行在文本中的起始字节偏移，用来把事件的字节偏移换算成行号，不用每次从头数换行
+struct LineIndex {
+    starts: Vec<usize>,
+}
+
+impl LineIndex {
+    fn new(text: &str) -> Self {
+        let starts = std::iter::once(0)
+            .chain(text.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        Self { starts }
+    }
+
+    /// 字节偏移所在的行号，从 1 开始
+    fn line(&self, offset: usize) -> usize {
+        self.starts.partition_point(|&start| start <= offset)
+    }
+}
+
+fn heading_level(level: HeadingLevel) -> u8 {
+    match level {
+        HeadingLevel::H1 => 1,
+        HeadingLevel::H2 => 2,
+        HeadingLevel::H3 => 3,
+        HeadingLevel::H4 => 4,
+        HeadingLevel::H5 => 5,
+        HeadingLevel::H6 => 6,
+    }
+}
+
+/// 把按出现顺序排列的标题组织成嵌套的目录
+fn build_toc(headings: Vec<TocEntry>) -> Vec<TocEntry> {
+    fn insert(entries: &mut Vec<TocEntry>, entry: TocEntry) {
+        match entries.last_mut() {
+            Some(last) if last.level < entry.level => insert(&mut last.children, entry),
+            _ => entries.push(entry),
+        }
+    }
+
+    let mut toc = Vec::new();
+    for entry in headings {
+        insert(&mut toc, entry);
+    }
+    toc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn line_index() {
+        let lines = LineIndex::new("a\nbc\n\nd");
+        let found: Vec<_> = [0, 1, 2, 4, 5, 6, 7].iter().map(|&offset| lines.line(offset)).collect();
+        assert_eq!(found, [1, 1, 2, 2, 3, 4, 4]);
+        assert_eq!(LineIndex::new("").line(0), 1);
+    }
+
+    #[test]
+    fn slugify_keeps_letters_and_cjk() {
+        assert_eq!(slugify("Hello, World!"), "hello-world");
+        assert_eq!(slugify("  Rust -- 异步 编程 "), "rust-异步-编程");
+        assert_eq!(slugify("snake_case & C++"), "snake_case-c");
+        assert_eq!(slugify("日本語の見出し"), "日本語の見出し");
+        assert_eq!(slugify("?!"), "section");
+    }
+
+    #[test]
+    fn unique_slug_numbers_duplicates() {
+        let mut slugs = HashSet::new();
+        let found: Vec<_> = ["a", "a", "a-1", "a"].iter().map(|slug| unique_slug(&mut slugs, slug)).collect();
+        assert_eq!(found, ["a", "a-1", "a-1-1", "a-2"]);
+    }
+
+    fn entry(level: u8, id: &str, title: &str, children: Vec<TocEntry>) -> TocEntry {
+        TocEntry { level, id: id.to_string(), title: title.to_string(), children }
+    }
+
+    #[tokio::test]
+    async fn toc_nests_headings() {
+        let md = "# 简介\n\n## Setup\n\n### Setup\n\n## `cargo` 用法\n\n# 简介\n\n#### Deep\n";
+        let rendered = MarkdownRenderer::new().render(md).await.unwrap();
+        assert_eq!(
+            rendered.toc,
+            [
+                entry(1, "简介", "简介", vec![
+                    entry(2, "setup", "Setup", vec![entry(3, "setup-1", "Setup", Vec::new())]),
+                    entry(2, "cargo-用法", "cargo 用法", Vec::new()),
+                ]),
+                entry(1, "简介-1", "简介", vec![entry(4, "deep", "Deep", Vec::new())]),
+            ]
+        );
+        assert!(rendered.html.contains(r#"<h3 id="setup-1">Setup</h3>"#));
+    }
+
+    #[tokio::test]
+    async fn math_headings_keep_their_source_in_the_toc() {
+        let rendered = MarkdownRenderer::new().render("## Euler $e^{i\\pi}$[^1]\n\n[^1]: note\n").await.unwrap();
+        assert_eq!(rendered.toc, [entry(2, "euler-eipi", "Euler e^{i\\pi}", Vec::new())]);
+        assert!(rendered.html.contains("<math"));
+    }
+
+    #[tokio::test]
+    async fn footnotes_are_numbered_by_first_reference() {
+        let md = "A[^b] B[^a] C[^b]\n\n[^a]: first\n[^b]: second\n[^unused]: never\n";
+        let html = MarkdownRenderer::new().render(md).await.unwrap().html;
+        assert_eq!(
+            html,
+            r##"<p>A<sup class="footnote-ref" id="fnref-1"><a href="#fn-1">1</a></sup> B<sup class="footnote-ref" id="fnref-2"><a href="#fn-2">2</a></sup> C<sup class="footnote-ref" id="fnref-1-2"><a href="#fn-1">1</a></sup></p>
+<section class="footnotes">
+<ol>
+<li id="fn-1">
+<p>second <a href="#fnref-1" class="footnote-backref">↩</a> <a href="#fnref-1-2" class="footnote-backref">↩</a></p>
+</li>
+<li id="fn-2">
+<p>first <a href="#fnref-2" class="footnote-backref">↩</a></p>
+</li>
+</ol>
+</section>
+"##
+        );
+    }
+
+    #[tokio::test]
+    async fn unreferenced_footnotes_are_dropped() {
+        let html = MarkdownRenderer::new().render("text\n\n[^a]: never\n").await.unwrap().html;
+        assert_eq!(html, "<p>text</p>\n");
+    }
+}
@@ -0,0 +1,106 @@
+use anyhow::{anyhow, Result};
+use katex::{Opts, OutputType};
+use serde::{Deserialize, Serialize};
+
+/// config.json 中的公式渲染配置
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct MathConfig {
+    /// 识别 `$...$` 和 `$$...$$`
+    pub enabled: bool,
+}
+
+impl Default for MathConfig {
+    fn default() -> Self {
+        Self { enabled: true }
+    }
+}
+
+/// 在 push 时把 TeX 公式渲染为 MathML，页面上不需要再跑 js
+pub struct MathRenderer {
+    inline: Opts,
+    display: Opts,
+}
+
+impl MathRenderer {
+    pub fn new() -> Result<Self> {
+        Ok(Self {
+            inline: Self::opts(false)?,
+            display: Self::opts(true)?,
+        })
+    }
+
+    fn opts(display_mode: bool) -> Result<Opts> {
+        Ok(Opts::builder()
+            .display_mode(display_mode)
+            .output_type(OutputType::Mathml)
+            .throw_on_error(true)
+            .build()?)
+    }
+
+    /// 渲染一个公式，TeX 有误时返回 KaTeX 给出的错误信息
+    pub fn render(&self, tex: &str, display: bool) -> Result<String> {
+        let opts = if display { &self.display } else { &self.inline };
+        katex::render_with_opts(tex, opts).map_err(|err| anyhow!(katex_message(err)))
+    }
+}
+
+/// KaTeX 的错误形如 `String("ParseError: KaTeX parse error: Undefined control sequence: \\foo at position 1: ...")`，
+/// 只保留中间的说明和出错位置，去掉后面带下划线标记的源码片段
+fn katex_message(err: katex::Error) -> String {
+    let katex::Error::JsExecError(detail) = err else {
+        return err.to_string();
+    };
+    let message = detail
+        .trim_start_matches("String(\"")
+        .trim_end_matches("\")")
+        .trim_start_matches("ParseError: ")
+        .trim_start_matches("KaTeX parse error: ")
+        .replace("\\\\", "\\");
+    let end = ["at position", "at end of input"]
+        .iter()
+        .filter_map(|marker| message.find(marker).map(|i| i + marker.len()))
+        .min()
+        .unwrap_or(message.len());
+    let position = message[end..]
+        .split(':')
+        .next()
+        .filter(|n| n.trim().chars().all(|c| c.is_ascii_digit()))
+        .unwrap_or("");
+    format!("{}{position}", &message[..end])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::markdown::MarkdownRenderer;
+
+    #[test]
+    fn inline_and_display_math() {
+        let math = MathRenderer::new().unwrap();
+        let inline = math.render("x^2", false).unwrap();
+        assert!(inline.contains("<math") && inline.contains("<msup>"), "{inline}");
+        assert!(!inline.contains(r#"display="block""#), "{inline}");
+        let display = math.render("x^2", true).unwrap();
+        assert!(display.contains("<math") && display.contains(r#"display="block""#), "{display}");
+    }
+
+    #[test]
+    fn katex_errors_are_trimmed() {
+        let math = MathRenderer::new().unwrap();
+        let err = math.render("\\frac{", false).unwrap_err();
+        assert_eq!(err.to_string(), "Unexpected end of input in a macro argument, expected '}' at end of input");
+        let err = math.render("\\foo", true).unwrap_err();
+        assert_eq!(err.to_string(), "Undefined control sequence: \\foo at position 1");
+    }
+
+    #[tokio::test]
+    async fn invalid_tex_becomes_a_warning() {
+        let rendered = MarkdownRenderer::new().render("ok $x$\n\nbad $\\foo{1}$ here\n").await.unwrap();
+        assert_eq!(rendered.warnings.len(), 1);
+        assert_eq!(rendered.warnings[0].line, 3);
+        assert_eq!(rendered.warnings[0].message, "invalid TeX `\\foo{1}`: Undefined control sequence: \\foo at position 1");
+        assert!(rendered.html.contains(r#"<code class="math-error">$\foo{1}$</code>"#), "{}", rendered.html);
+        assert!(rendered.html.contains("<math"), "{}", rendered.html);
+    }
+}
@@ -0,0 +1,266 @@
+use std::collections::{HashMap, HashSet};
+
+use jieba_rs::Jieba;
+use lazy_static::lazy_static;
+
+use crate::data_struct::Essay;
+
+lazy_static! {
+    static ref JIEBA: Jieba = Jieba::new();
+}
+
+const TITLE_WEIGHT: f64 = 5.0;
+const BRIEF_WEIGHT: f64 = 2.0;
+const CONTENT_WEIGHT: f64 = 1.0;
+const MAX_TERM_LEN: usize = 64;
+const SNIPPET_RADIUS: usize = 40;
+const SNIPPET_LEN: usize = 160;
+/// 除了字母和数字，词中还可以有的字符，和 jieba 的规则一致，保留 `c++`, `c#`, `node.js` 这样的词
+const WORD_CHARS: &str = "+#&._%-";
+/// 非中文的词首尾去掉的字符，例如句末的 `.`
+const TRIMMED_CHARS: &[char] = &['.', '_', '-', '%', '&'];
+
+/// 分词：先按空白和标点切开，含有汉字的片段按 jieba 的搜索引擎模式切分，
+/// 其它片段整个作为一个词 (jieba 会把 `café` 这样带重音的词拆开)，统一小写，丢掉纯标点
+pub fn tokenize(text: &str) -> Vec<String> {
+    text.split(|c: char| !is_word_char(c))
+        .flat_map(|chunk| {
+            if chunk.chars().any(is_han) {
+                JIEBA.cut_for_search(chunk, true)
+            } else {
+                vec![chunk.trim_matches(TRIMMED_CHARS)]
+            }
+        })
+        .filter(|word| word.chars().any(char::is_alphanumeric))
+        .filter(|word| word.chars().count() <= MAX_TERM_LEN)
+        .map(lowercase)
+        .collect()
+}
+
+/// 去重后的查询词
+pub fn query_terms(q: &str) -> Vec<String> {
+    let mut seen = HashSet::new();
+    tokenize(q)
+        .into_iter()
+        .filter(|term| seen.insert(term.clone()))
+        .collect()
+}
+
+/// 计算一篇文章的索引词以及权重，标题和简介中的词权重更高
+pub fn essay_terms(essay: &Essay) -> HashMap<String, f64> {
+    let mut counts: HashMap<String, f64> = HashMap::new();
+    let fields = [
+        (essay.title.as_str(), TITLE_WEIGHT),
+        (essay.brief.as_str(), BRIEF_WEIGHT),
+        (&strip_html(&essay.content), CONTENT_WEIGHT),
+    ];
+    for (text, weight) in fields {
+        for term in tokenize(text) {
+            *counts.entry(term).or_default() += weight;
+        }
+    }
+    counts
+        .into_iter()
+        .map(|(term, count)| (term, (1.0 + count).ln()))
+        .collect()
+}
+
+/// 根据 (eid, term, weight) 命中记录给文章打分，`total` 为文章总数。
+/// 返回按分数从高到低排好序的 (eid, score)
+pub fn rank(hits: &[(String, String, f64)], total: u64) -> Vec<(String, f64)> {
+    let mut df: HashMap<&str, f64> = HashMap::new();
+    for (_, term, _) in hits {
+        *df.entry(term).or_default() += 1.0;
+    }
+    let total = total as f64;
+    let mut scores: HashMap<&str, f64> = HashMap::new();
+    for (eid, term, weight) in hits {
+        let df = df[term.as_str()];
+        let idf = (1.0 + (total - df + 0.5) / (df + 0.5)).ln();
+        *scores.entry(eid).or_default() += weight * idf;
+    }
+    let mut res: Vec<(String, f64)> = scores
+        .into_iter()
+        .map(|(eid, score)| (eid.to_string(), score))
+        .collect();
+    res.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    res
+}
+
+/// 去掉 html 标签，得到纯文本
+pub fn strip_html(html: &str) -> String {
+    let mut text = String::with_capacity(html.len());
+    let mut in_tag = false;
+    for c in html.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => {
+                in_tag = false;
+                text.push(' ');
+            },
+            _ if !in_tag => text.push(c),
+            _ => {},
+        }
+    }
+    let text = text
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&");
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// 从纯文本中截取第一个命中附近的一段，转义后用 `<mark>` 标出命中的词
+pub fn snippet(text: &str, terms: &[String]) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let lower: Vec<char> = chars.iter().map(|c| lower_char(*c)).collect();
+    let mut terms: Vec<Vec<char>> = terms
+        .iter()
+        .map(|term| term.chars().map(lower_char).collect::<Vec<_>>())
+        .filter(|term| !term.is_empty())
+        .collect();
+    terms.sort_by_key(|term| std::cmp::Reverse(term.len()));
+
+    let match_at = |i: usize| {
+        terms
+            .iter()
+            .find(|term| lower[i..].starts_with(term))
+            .map(|term| term.len())
+    };
+    let first = (0..lower.len()).find(|i| match_at(*i).is_some()).unwrap_or(0);
+    let start = first.saturating_sub(SNIPPET_RADIUS);
+    let end = (start + SNIPPET_LEN).min(chars.len());
+
+    let mut res = String::new();
+    if start > 0 {
+        res.push('…');
+    }
+    let mut i = start;
+    while i < end {
+        match match_at(i).filter(|len| i + len <= end) {
+            Some(len) => {
+                res.push_str("<mark>");
+                chars[i..i + len].iter().for_each(|c| push_escaped(&mut res, *c));
+                res.push_str("</mark>");
+                i += len;
+            },
+            None => {
+                push_escaped(&mut res, chars[i]);
+                i += 1;
+            },
+        }
+    }
+    if end < chars.len() {
+        res.push('…');
+    }
+    res
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || WORD_CHARS.contains(c)
+}
+
+/// CJK 统一汉字，包括扩展 A
+fn is_han(c: char) -> bool {
+    matches!(c, '\u{3400}'..='\u{4DBF}' | '\u{4E00}'..='\u{9FFF}')
+}
+
+fn lowercase(word: &str) -> String {
+    word.chars().map(lower_char).collect()
+}
+
+/// 逐字符转小写，保证转换前后字符数一致
+fn lower_char(c: char) -> char {
+    c.to_lowercase().next().unwrap_or(c)
+}
+
+fn push_escaped(buf: &mut String, c: char) {
+    match c {
+        '<' => buf.push_str("&lt;"),
+        '>' => buf.push_str("&gt;"),
+        '&' => buf.push_str("&amp;"),
+        '"' => buf.push_str("&quot;"),
+        _ => buf.push(c),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn terms(terms: &[&str]) -> Vec<String> {
+        terms.iter().map(|term| term.to_string()).collect()
+    }
+
+    #[test]
+    fn tokenize_lowercases_and_drops_punctuation() {
+        assert_eq!(tokenize("Hello, World!  The end."), ["hello", "world", "the", "end"]);
+        assert_eq!(tokenize("C++ and C# on node.js"), ["c++", "and", "c#", "on", "node.js"]);
+        assert!(tokenize("—— …… !!").is_empty());
+    }
+
+    #[test]
+    fn tokenize_keeps_accented_words_whole() {
+        assert_eq!(tokenize("Café résumé ÀB Straße"), ["café", "résumé", "àb", "straße"]);
+    }
+
+    #[test]
+    fn tokenize_cuts_chinese_for_search() {
+        let res = tokenize("Rust 数据库的全文搜索");
+        assert_eq!(res[0], "rust");
+        for term in ["数据", "数据库", "全文", "搜索"] {
+            assert!(res.iter().any(|t| t == term), "missing {term} in {res:?}");
+        }
+        assert!(res.iter().all(|term| !term.contains(' ')));
+    }
+
+    #[test]
+    fn tokenize_drops_overlong_words() {
+        let long = "a".repeat(MAX_TERM_LEN + 1);
+        assert_eq!(tokenize(&format!("short {long}")), ["short"]);
+    }
+
+    #[test]
+    fn query_terms_are_unique() {
+        assert_eq!(query_terms("rust Rust RUST 搜索"), ["rust", "搜索"]);
+    }
+
+    #[test]
+    fn rank_prefers_rare_terms_and_breaks_ties_by_eid() {
+        let hits = vec![
+            (String::from("b"), String::from("common"), 1.0),
+            (String::from("a"), String::from("common"), 1.0),
+            (String::from("c"), String::from("common"), 1.0),
+            (String::from("c"), String::from("rare"), 1.0),
+        ];
+        let res = rank(&hits, 10);
+        let eids: Vec<_> = res.iter().map(|(eid, _)| eid.as_str()).collect();
+        assert_eq!(eids, ["c", "a", "b"]);
+        assert!(res[0].1 > res[1].1);
+        assert_eq!(res[1].1, res[2].1);
+        assert!(rank(&[], 10).is_empty());
+    }
+
+    #[test]
+    fn strip_html_keeps_text_only() {
+        assert_eq!(strip_html("<p>a &lt;b&gt;</p>\n<p>c &amp; d</p>"), "a <b> c & d");
+    }
+
+    #[test]
+    fn snippet_marks_and_escapes_multibyte_text() {
+        let res = snippet("提到了数据库和 <Rust> & 更多", &terms(&["数据库", "rust"]));
+        assert_eq!(res, "提到了<mark>数据库</mark>和 &lt;<mark>Rust</mark>&gt; &amp; 更多");
+    }
+
+    #[test]
+    fn snippet_cuts_around_first_hit() {
+        let text = format!("{}命中{}", "前".repeat(100), "后".repeat(200));
+        let res = snippet(&text, &terms(&["命中"]));
+        assert!(res.starts_with('…') && res.ends_with('…'));
+        assert!(res.contains("<mark>命中</mark>"));
+        assert_eq!(res.chars().filter(|c| *c == '前').count(), SNIPPET_RADIUS);
+        // 没有命中时从头开始
+        assert!(snippet(&text, &terms(&["没有"])).starts_with('前'));
+    }
+}
@@ -0,0 +1,424 @@
+use std::collections::{HashMap, HashSet};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::Serialize;
+use sqlx::{Connection, Database};
+
+use crate::data_struct::{Essay, MarkdownRenderer};
+
+/// 本地的一篇文章
+#[derive(Debug, Clone)]
+pub struct LocalEssay {
+    pub path: String,
+    pub essay: Essay,
+    /// 源文件的 hash
+    pub content_hash: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SyncAction {
+    Insert,
+    Update,
+    Delete,
+}
+
+impl SyncAction {
+    /// 日志中使用的名字
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Insert => "INSERT",
+            Self::Update => "UPDATE",
+            Self::Delete => "DELETE",
+        }
+    }
+}
+
+/// 一项计划中的变更
+#[derive(Debug, Clone)]
+pub enum SyncChange {
+    Insert(LocalEssay),
+    Update(LocalEssay),
+    Delete { eid: String, title: String },
+}
+
+impl SyncChange {
+    pub fn action(&self) -> SyncAction {
+        match self {
+            Self::Insert(_) => SyncAction::Insert,
+            Self::Update(_) => SyncAction::Update,
+            Self::Delete { .. } => SyncAction::Delete,
+        }
+    }
+
+    pub fn eid(&self) -> &str {
+        match self {
+            Self::Insert(local) | Self::Update(local) => &local.essay.eid,
+            Self::Delete { eid, .. } => eid,
+        }
+    }
+
+    pub fn title(&self) -> &str {
+        match self {
+            Self::Insert(local) | Self::Update(local) => &local.essay.title,
+            Self::Delete { title, .. } => title,
+        }
+    }
+
+    pub fn path(&self) -> Option<&str> {
+        match self {
+            Self::Insert(local) | Self::Update(local) => Some(&local.path),
+            Self::Delete { .. } => None,
+        }
+    }
+}
+
+/// 同步计划，删除排在最前面，其余按文件路径排序
+#[derive(Debug, Clone, Default)]
+pub struct SyncPlan {
+    pub changes: Vec<SyncChange>,
+    /// 没有变化的文章数
+    pub unchanged: usize,
+}
+
+/// 读取一批文章，读取失败的文章记录在 `errors` 中
+#[derive(Debug, Clone, Default)]
+pub struct LoadedBatch {
+    pub essays: Vec<LocalEssay>,
+    /// `path:line: message`
+    pub warnings: Vec<String>,
+    pub errors: Vec<SyncError>,
+}
+
+/// 读取一批文章。eid 和前面某个文件重复的文章作为错误，错误中给出两个文件的路径；
+/// 没有 eid 的文章 eid 为空，由调用者生成
+pub async fn load_essays(paths: Vec<String>, renderer: &MarkdownRenderer) -> LoadedBatch {
+    let mut res = LoadedBatch::default();
+    // eid → 第一次出现的文件
+    let mut eids: HashMap<String, String> = HashMap::new();
+    for path in paths {
+        match Essay::load_from_path(&path, renderer).await {
+            Ok(loaded) => {
+                res.warnings.extend(
+                    loaded
+                        .warnings
+                        .into_iter()
+                        .map(|warning| format!("{path}:{}: {}", warning.line, warning.message)),
+                );
+                let eid = &loaded.essay.eid;
+                if let Some(first) = eids.get(eid).filter(|_| !eid.is_empty()) {
+                    res.errors.push(SyncError {
+                        message: format!("duplicate eid `{eid}` in {first} and {path}"),
+                        path: Some(path),
+                        eid: Some(eid.clone()),
+                    });
+                    continue;
+                }
+                eids.insert(eid.clone(), path.clone());
+                res.essays.push(LocalEssay { path, essay: loaded.essay, content_hash: loaded.content_hash });
+            },
+            Err(err) => res.errors.push(SyncError { path: Some(path), eid: None, message: format!("{err:#}") }),
+        }
+    }
+    res
+}
+
+/// 对比本地所有文章和数据库中的 (标题, 源文件 hash)，得到完整的同步计划，数据库中多出来的文章会被删除。
+/// `allow_delete` 为 `false` 时不删除任何文章，用于有文件读取失败、无法确定哪些文章真正被删除的情况；
+/// `force` 为 `true` 时更新所有已经存在的文章
+pub fn plan(
+    local: Vec<LocalEssay>,
+    remote: &HashMap<String, (String, Option<String>)>,
+    allow_delete: bool,
+    force: bool,
+) -> SyncPlan {
+    let deleted = match allow_delete {
+        true => {
+            let local_eids: HashSet<_> = local.iter().map(|local| local.essay.eid.as_str()).collect();
+            remote
+                .keys()
+                .filter(|eid| !local_eids.contains(eid.as_str()))
+                .cloned()
+                .collect()
+        },
+        false => Vec::new(),
+    };
+    plan_changes(local, deleted, remote, force)
+}
+
+/// 只针对部分文章的同步计划：`local` 中 hash 不同的文章更新，`deleted` 中还在数据库里的文章删除
+pub fn plan_changes(
+    mut local: Vec<LocalEssay>,
+    deleted: Vec<String>,
+    remote: &HashMap<String, (String, Option<String>)>,
+    force: bool,
+) -> SyncPlan {
+    let mut res = SyncPlan::default();
+    let mut deleted: Vec<_> = deleted
+        .into_iter()
+        .filter_map(|eid| {
+            let title = remote.get(&eid)?.0.clone();
+            Some(SyncChange::Delete { eid, title })
+        })
+        .collect();
+    deleted.sort_by(|a, b| a.eid().cmp(b.eid()));
+    res.changes.extend(deleted);
+
+    local.sort_by(|a, b| a.path.cmp(&b.path));
+    for essay in local {
+        match remote.get(&essay.essay.eid) {
+            None => res.changes.push(SyncChange::Insert(essay)),
+            Some((_, content_hash)) if force || content_hash.as_deref() != Some(essay.content_hash.as_str()) => {
+                res.changes.push(SyncChange::Update(essay))
+            },
+            Some(_) => res.unchanged += 1,
+        }
+    }
+    res
+}
+
+/// 同步时对数据库的写入，由 [`apply`] 在一个事务中调用。MySQL 和 SQLite 的连接各有一份实现
+#[async_trait]
+pub trait SyncWrite: Send {
+    /// 批量删除文章以及它们的 tags, categories 和全文索引词
+    async fn delete_essays(&mut self, eids: &[&str]) -> Result<()>;
+    /// 把文章当前的版本存入历史版本，和 `essays` 中新版本的 [`Essay::version_hash`] 相同的文章跳过
+    async fn archive_essays(&mut self, essays: &[&Essay], current_time: f64) -> Result<()>;
+    /// 批量删除文章的 tags, categories 和全文索引词
+    async fn delete_essay_links(&mut self, eids: &[&str]) -> Result<()>;
+    /// 写入 essays 表中的一行
+    async fn insert_essay_info(&mut self, essay: &Essay, content_hash: &str, current_time: f64) -> Result<()>;
+    /// 更新 essays 表中的一行
+    async fn update_essay_info(&mut self, essay: &Essay, content_hash: &str, current_time: f64) -> Result<()>;
+    /// 批量写入文章的 tags, categories 和全文索引词
+    async fn insert_essay_links(&mut self, essays: &[&Essay]) -> Result<()>;
+}
+
+/// 在一个事务中按计划写入数据库，任何一步失败都会回滚整个事务，返回的错误指明失败的文章。
+/// 删除、历史版本和 tags, categories, 索引词都是批量写入，只有 essays 表中的行逐篇写入
+pub async fn apply<C>(
+    conn: &mut C,
+    plan: &SyncPlan,
+    current_time: f64,
+) -> Result<(), SyncError>
+where
+    C: Connection + SyncWrite,
+    C::Database: Database<Connection = C>,
+{
+    let mut tx = conn.begin().await.map_err(SyncError::database)?;
+    let tx_conn: &mut C = &mut tx;
+    let batch_error = |step: &str, err: anyhow::Error| SyncError {
+        path: None,
+        eid: None,
+        message: format!("{step} failed, all changes rolled back: {err:#}"),
+    };
+
+    let mut deleted = Vec::new();
+    let mut updated = Vec::new();
+    let mut written = Vec::new();
+    for change in &plan.changes {
+        match change {
+            SyncChange::Insert(local) => written.push(&local.essay),
+            SyncChange::Update(local) => {
+                updated.push(&local.essay);
+                written.push(&local.essay);
+            },
+            SyncChange::Delete { eid, .. } => deleted.push(eid.as_str()),
+        }
+    }
+    // 提前返回时 tx 被 drop，事务自动回滚
+    tx_conn
+        .delete_essays(&deleted)
+        .await
+        .map_err(|err| batch_error("DELETE", err))?;
+    tx_conn
+        .archive_essays(&updated, current_time)
+        .await
+        .map_err(|err| batch_error("archiving revisions", err))?;
+    let updated_eids: Vec<_> = updated.iter().map(|essay| essay.eid.as_str()).collect();
+    tx_conn
+        .delete_essay_links(&updated_eids)
+        .await
+        .map_err(|err| batch_error("clearing tags, categories and search terms", err))?;
+
+    for change in &plan.changes {
+        let res = match change {
+            SyncChange::Insert(local) => tx_conn.insert_essay_info(&local.essay, &local.content_hash, current_time).await,
+            SyncChange::Update(local) => tx_conn.update_essay_info(&local.essay, &local.content_hash, current_time).await,
+            SyncChange::Delete { .. } => continue,
+        };
+        res.map_err(|err| SyncError {
+            path: change.path().map(String::from),
+            eid: Some(change.eid().to_string()),
+            message: format!("{} failed, all changes rolled back: {err:#}", change.action().label()),
+        })?;
+    }
+    tx_conn
+        .insert_essay_links(&written)
+        .await
+        .map_err(|err| batch_error("writing tags, categories and search terms", err))?;
+
+    tx.commit().await.map_err(SyncError::database)?;
+    Ok(())
+}
+
+/// 一次同步的结果，可以输出为 json 供部署脚本检查。
+/// 不是 dry run 且 `errors` 不为空时，这次同步没有写入任何内容
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SyncReport {
+    pub dry_run: bool,
+    pub counts: SyncCounts,
+    pub changes: Vec<ReportedChange>,
+    pub warnings: Vec<String>,
+    pub errors: Vec<SyncError>,
+}
+
+/// 计划中各类变更的数量
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SyncCounts {
+    pub insert: usize,
+    pub update: usize,
+    pub delete: usize,
+    pub unchanged: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ReportedChange {
+    pub action: SyncAction,
+    pub eid: String,
+    pub title: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SyncError {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub eid: Option<String>,
+    pub message: String,
+}
+
+impl SyncError {
+    pub(crate) fn database(err: sqlx::Error) -> Self {
+        Self {
+            path: None,
+            eid: None,
+            message: format!("database error, all changes rolled back: {err}"),
+        }
+    }
+}
+
+impl SyncReport {
+    pub fn new(dry_run: bool, plan: &SyncPlan) -> Self {
+        let mut res = Self {
+            dry_run,
+            ..Default::default()
+        };
+        res.counts.unchanged = plan.unchanged;
+        for change in &plan.changes {
+            match change.action() {
+                SyncAction::Insert => res.counts.insert += 1,
+                SyncAction::Update => res.counts.update += 1,
+                SyncAction::Delete => res.counts.delete += 1,
+            }
+            res.changes.push(ReportedChange {
+                action: change.action(),
+                eid: change.eid().to_string(),
+                title: change.title().to_string(),
+                path: change.path().map(String::from),
+            });
+        }
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn local(path: &str, eid: &str, content_hash: &str) -> LocalEssay {
+        let essay = Essay::new(
+            eid.to_string(),
+            format!("title {eid}"),
+            String::from("2024-01-05 09:00:00"),
+            Vec::new(),
+            Vec::new(),
+            String::new(),
+            String::new(),
+        );
+        LocalEssay { path: path.to_string(), essay, content_hash: content_hash.to_string() }
+    }
+
+    /// eid → (标题, 源文件 hash)
+    fn remote(essays: &[(&str, Option<&str>)]) -> HashMap<String, (String, Option<String>)> {
+        essays
+            .iter()
+            .map(|(eid, hash)| (eid.to_string(), (format!("title {eid}"), hash.map(String::from))))
+            .collect()
+    }
+
+    fn changes(plan: &SyncPlan) -> Vec<(SyncAction, &str)> {
+        plan.changes.iter().map(|change| (change.action(), change.eid())).collect()
+    }
+
+    #[test]
+    fn plan_compares_content_hashes() {
+        let local = vec![
+            local("c.md", "new", "h1"),
+            local("b.md", "changed", "h2"),
+            local("a.md", "same", "h3"),
+            local("d.md", "unhashed", "h4"),
+        ];
+        let remote = remote(&[
+            ("same", Some("h3")),
+            ("changed", Some("old")),
+            ("unhashed", None),
+            ("gone", Some("h5")),
+            ("also_gone", None),
+        ]);
+        let plan = plan(local, &remote, true, false);
+        assert_eq!(
+            changes(&plan),
+            [
+                (SyncAction::Delete, "also_gone"),
+                (SyncAction::Delete, "gone"),
+                (SyncAction::Update, "changed"),
+                (SyncAction::Insert, "new"),
+                (SyncAction::Update, "unhashed"),
+            ]
+        );
+        assert_eq!(plan.unchanged, 1);
+        assert_eq!(plan.changes[0].title(), "title also_gone");
+    }
+
+    #[test]
+    fn plan_keeps_remote_essays_without_allow_delete() {
+        let remote = remote(&[("a", Some("h1")), ("gone", Some("h2"))]);
+        let plan = plan(vec![local("a.md", "a", "h1")], &remote, false, false);
+        assert!(plan.changes.is_empty());
+        assert_eq!(plan.unchanged, 1);
+    }
+
+    #[test]
+    fn force_updates_unchanged_essays() {
+        let remote = remote(&[("a", Some("h1"))]);
+        let plan = plan(vec![local("a.md", "a", "h1"), local("b.md", "b", "h2")], &remote, true, true);
+        assert_eq!(changes(&plan), [(SyncAction::Update, "a"), (SyncAction::Insert, "b")]);
+        assert_eq!(plan.unchanged, 0);
+    }
+
+    #[test]
+    fn plan_changes_only_deletes_known_essays() {
+        let remote = remote(&[("a", Some("h1")), ("b", Some("h2"))]);
+        let deleted = vec![String::from("b"), String::from("unknown")];
+        let plan = plan_changes(vec![local("a.md", "a", "h1")], deleted, &remote, false);
+        assert_eq!(changes(&plan), [(SyncAction::Delete, "b")]);
+        assert_eq!(plan.unchanged, 1);
+
+        let report = SyncReport::new(true, &plan);
+        assert_eq!((report.counts.delete, report.counts.unchanged), (1, 1));
+    }
+}
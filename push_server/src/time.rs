@@ -0,0 +1,25 @@
+use chrono::NaiveDateTime;
+
+/// front matter 中 `date` 和 `publish_at` 的格式，都是运行博客服务的服务器的本地时间
+pub const DATE_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+/// 按 [`DATE_FORMAT`] 解析后重新格式化，统一补齐前导零 (例如 `2024-1-5 9:00:00`)，
+/// 保证和 [`crate::now_date`] 按字符串比较时结果正确。不能解析时返回 `None`
+pub fn normalize_datetime(value: &str) -> Option<String> {
+    NaiveDateTime::parse_from_str(value.trim(), DATE_FORMAT)
+        .ok()
+        .map(|datetime| datetime.format(DATE_FORMAT).to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalize_datetime_pads_fields() {
+        assert_eq!(normalize_datetime("2024-1-5 9:00:00").as_deref(), Some("2024-01-05 09:00:00"));
+        assert_eq!(normalize_datetime(" 2024-01-05 09:00:00 ").as_deref(), Some("2024-01-05 09:00:00"));
+        assert_eq!(normalize_datetime("2024-01-05"), None);
+        assert_eq!(normalize_datetime("2024-01-05T09:00:00+08:00"), None);
+    }
+}
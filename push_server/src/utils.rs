@@ -1,36 +1,111 @@
 use std::fs;
-use std::time::UNIX_EPOCH;
 
-use anyhow::Result;
+use push_server::{
+    data_struct::content_hash,
+    front_matter::FrontMatter,
+    sync::{LoadedBatch, SyncError},
+};
 use uuid::Uuid;
 
-/// 递归得到 `dir` 文件夹下所有后缀为 `suffix` 的文件路径。
-pub fn get_entries(dir: &str, suffix: &str) -> Vec<String> {
-    match fs::read_dir(dir) {
-        Ok(entries) => {
-            let mut res: Vec<String> = Vec::new();
-            for i in entries {
-                let path = i.unwrap().path();
-                if path.is_dir() {
-                    res.append(&mut get_entries(path.to_str().unwrap(), suffix));
-                } else if path.extension().unwrap() == suffix {
-                    res.push(path.to_str().unwrap().to_string());
-                }
+/// 得到一个 uuid
+pub fn get_uuid() -> String {
+    Uuid::new_v4().to_string()
+}
+
+/// 给没有 eid 的文章生成一个 eid，插入到 front matter 的第一行写回文件，文件的其余部分保持原样。
+/// `dry_run` 时只在内存中生成，不写文件。写回失败的文章移到 `errors` 中。返回生成了 eid 的文章
+pub fn assign_eids(batch: &mut LoadedBatch, dry_run: bool) -> Vec<(String, String)> {
+    let mut res = Vec::new();
+    let mut failed = Vec::new();
+    for (i, local) in batch.essays.iter_mut().enumerate() {
+        if !local.essay.eid.is_empty() {
+            continue;
+        }
+        let eid = get_uuid();
+        if !dry_run {
+            let written = fs::read_to_string(&local.path)
+                .map_err(anyhow::Error::from)
+                .and_then(|content| Ok(FrontMatter::insert_field(&content, "eid", &eid)?))
+                .and_then(|content| {
+                    fs::write(&local.path, &content)?;
+                    Ok(content)
+                });
+            match written {
+                Ok(content) => local.content_hash = content_hash(&content),
+                Err(err) => {
+                    failed.push((i, format!("{}: cannot write eid: {err:#}", local.path)));
+                    continue;
+                },
             }
-            res
-        },
-        Err(_) => Vec::new(),
+        }
+        local.essay.eid = eid.clone();
+        res.push((local.path.clone(), eid));
     }
+    for (i, message) in failed.into_iter().rev() {
+        let local = batch.essays.remove(i);
+        batch.errors.push(SyncError { path: Some(local.path), eid: None, message });
+    }
+    res
 }
 
-/// 得到该路径的文件的最后一次修改时间
-pub fn get_modified_time(path: &str) -> Result<f64> {
-    let metadata = fs::metadata(&path)?;
-    let modified_time = metadata.modified()?.duration_since(UNIX_EPOCH).unwrap().as_secs_f64();
-    Ok(modified_time)
-}
+#[cfg(test)]
+mod tests {
+    use push_server::{data_struct::MarkdownRenderer, sync::load_essays};
 
-/// 得到一个 uuid
-pub fn get_uuid() -> String {
-    Uuid::new_v4().to_string()
-}
\ No newline at end of file
+    use super::*;
+
+    #[tokio::test]
+    async fn assign_eids_to_new_essays() {
+        let dir = std::env::temp_dir().join(format!("rusite-eids-{}", get_uuid()));
+        fs::create_dir_all(&dir).unwrap();
+        let fields = "title: \"a\"\ndate: \"2024-01-01 00:00:00\"\ncategories: []\ntags: []\nbrief: \"b\"\n";
+        let files = [
+            ("new.md", format!("---\n{fields}---\nbody\n")),
+            ("empty.md", format!("---\neid: \"\"\n{fields}---\nbody\n").replace('\n', "\r\n")),
+            (
+                "toml.md",
+                "+++\ntitle = \"a\"\ndate = 2024-01-01T00:00:00\ncategories = []\ntags = []\nbrief = \"b\"\n+++\nbody\n"
+                    .to_string(),
+            ),
+            ("old.md", format!("---\neid: \"old\"\n{fields}---\nbody\n")),
+        ];
+        let mut paths = Vec::new();
+        for (name, content) in &files {
+            let path = dir.join(name).to_string_lossy().to_string();
+            fs::write(&path, content).unwrap();
+            paths.push(path);
+        }
+        let renderer = MarkdownRenderer::new();
+
+        // dry run 只在内存中生成
+        let mut batch = load_essays(paths.clone(), &renderer).await;
+        let assigned = assign_eids(&mut batch, true);
+        assert_eq!(assigned.len(), 3);
+        for (path, (_, content)) in paths.iter().zip(&files) {
+            assert_eq!(&fs::read_to_string(path).unwrap(), content);
+        }
+
+        let mut batch = load_essays(paths.clone(), &renderer).await;
+        let assigned = assign_eids(&mut batch, false);
+        assert_eq!(assigned.len(), 3);
+        assert!(batch.errors.is_empty());
+
+        // 重新读取得到同样的 eid 和 hash，再同步一次不会有变化
+        let reloaded = load_essays(paths.clone(), &renderer).await;
+        assert!(reloaded.errors.is_empty(), "{:?}", reloaded.errors);
+        for (written, reloaded) in batch.essays.iter().zip(&reloaded.essays) {
+            assert_eq!(written.essay.eid, reloaded.essay.eid);
+            assert_eq!(written.content_hash, reloaded.content_hash);
+        }
+        assert_eq!(reloaded.essays[3].essay.eid, "old");
+        assert!(assign_eids(&mut load_essays(paths.clone(), &renderer).await, false).is_empty());
+
+        let empty = fs::read_to_string(&paths[1]).unwrap();
+        assert_eq!(empty.matches("eid:").count(), 1);
+        assert!(empty.starts_with("---\r\neid: \""));
+        assert!(empty.ends_with("---\r\nbody\r\n"));
+        assert!(fs::read_to_string(&paths[2]).unwrap().starts_with("+++\neid = \""));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
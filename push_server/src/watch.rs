@@ -0,0 +1,192 @@
+use std::{collections::{HashMap, HashSet}, path::Path, time::Duration};
+
+use anyhow::Result;
+use notify_debouncer_mini::{new_debouncer, notify::RecursiveMode, DebounceEventResult, DebouncedEvent};
+use push_server::{
+    data_struct::MarkdownRenderer,
+    dbops::store::EssayStore,
+    get_entries, now,
+    sync::{self, load_essays, LoadedBatch, LocalEssay, SyncError, SyncPlan},
+};
+use tokio::sync::mpsc;
+
+use crate::{change_summary, connect, utils, Config};
+
+/// 编辑器保存时往往连续产生多个事件，合并这段时间内的事件后再同步
+const DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// 先做一次完整同步，然后监听 `essays_source`，只同步新建、修改、重命名、删除的文章。
+/// 同步失败只打印错误，继续监听
+pub async fn watch(config: &Config, renderer: &MarkdownRenderer) -> Result<()> {
+    renderer.write_highlight_css(&config.renderer).await?;
+    let store = connect(|label, detail| println!("->> {:<12} - {detail}", label)).await?;
+    // 通知中的路径都是绝对路径，文件列表也使用绝对路径，两边才能对应上
+    let root = std::fs::canonicalize(&config.essays_source)?.to_string_lossy().to_string();
+
+    // 文件路径 → eid，用于在文件被删除、重命名后找到对应的文章
+    let mut known = HashMap::new();
+    let mut batch = load_essays(get_entries(&root, "md")?, renderer).await;
+    log_batch(&batch);
+    assign_eids(&mut batch);
+    for essay in &batch.essays {
+        known.insert(essay.path.clone(), essay.essay.eid.clone());
+    }
+    let allow_delete = batch.errors.is_empty();
+    if let Err(err) = write(store.as_ref(), |remote| sync::plan(batch.essays, remote, allow_delete, false)).await {
+        println!("->> {:<12} - {err:#}", "ERROR");
+    }
+
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let mut debouncer = new_debouncer(DEBOUNCE, move |res: DebounceEventResult| {
+        let _ = tx.send(res);
+    })?;
+    debouncer
+        .watcher()
+        .watch(Path::new(&root), RecursiveMode::Recursive)?;
+    println!("->> {:<12} - {root}", "WATCH");
+
+    while let Some(res) = rx.recv().await {
+        let events = match res {
+            Ok(events) => events,
+            Err(err) => {
+                println!("->> {:<12} - {err}", "ERROR");
+                continue;
+            },
+        };
+        let paths = affected_paths(&events, &known);
+        if paths.is_empty() {
+            continue;
+        }
+        if let Err(err) = sync_paths(store.as_ref(), renderer, paths, &mut known).await {
+            println!("->> {:<12} - {err:#}", "ERROR");
+        }
+    }
+    Ok(())
+}
+
+/// 事件涉及的 markdown 文件。目录被移入时包括其中所有文件，目录被移走或删除时包括原来在其中的所有文件
+fn affected_paths(events: &[DebouncedEvent], known: &HashMap<String, String>) -> Vec<String> {
+    let mut res = Vec::new();
+    for event in events {
+        let path = event.path.to_string_lossy().to_string();
+        if event.path.extension().is_some_and(|ext| ext == "md") {
+            res.push(path);
+        } else if event.path.is_dir() {
+            // 目录在这之后又被移走时忽略，它的删除事件会单独处理
+            res.extend(get_entries(&path, "md").unwrap_or_default());
+        } else {
+            // 按路径的组成部分比较，不依赖分隔符，`a/b` 也不会匹配 `a/bc/x.md`
+            res.extend(known.keys().filter(|known| Path::new(known).starts_with(&event.path)).cloned());
+        }
+    }
+    res.sort();
+    res.dedup();
+    res
+}
+
+/// 同步一批变化的文件：还存在的文件重新读取后按 hash 更新，不存在的文件对应的文章被删除，
+/// 除非同一个 eid 还在别的文件中 (例如文件被重命名)
+async fn sync_paths(
+    store: &dyn EssayStore,
+    renderer: &MarkdownRenderer,
+    paths: Vec<String>,
+    known: &mut HashMap<String, String>,
+) -> Result<()> {
+    let (existing, removed): (Vec<_>, Vec<_>) = paths.into_iter().partition(|path| Path::new(path).is_file());
+    let mut batch = load_essays(existing, renderer).await;
+    let mut candidates: Vec<_> = removed.iter().filter_map(|path| known.remove(path)).collect();
+    // 这一批之外的文件已经在用同一个 eid 时拒绝这篇文章
+    let loaded: HashSet<_> = batch.essays.iter().map(|essay| essay.path.clone()).collect();
+    let other_path = |essay: &LocalEssay| {
+        known
+            .iter()
+            .find(|(path, eid)| **eid == essay.essay.eid && !loaded.contains(*path))
+            .map(|(path, _)| path.clone())
+    };
+    let (duplicated, essays): (Vec<_>, Vec<_>) =
+        batch.essays.into_iter().partition(|essay| other_path(essay).is_some());
+    batch.essays = essays;
+    for essay in duplicated {
+        let first = other_path(&essay).unwrap_or_default();
+        batch.errors.push(SyncError {
+            message: format!("duplicate eid `{}` in {first} and {}", essay.essay.eid, essay.path),
+            path: Some(essay.path),
+            eid: Some(essay.essay.eid),
+        });
+    }
+    log_batch(&batch);
+    assign_eids(&mut batch);
+
+    for essay in &batch.essays {
+        if let Some(old) = known.insert(essay.path.clone(), essay.essay.eid.clone()) {
+            if old != essay.essay.eid {
+                candidates.push(old);
+            }
+        }
+    }
+    candidates.retain(|eid| !known.values().any(|known| known == eid));
+
+    write(store, |remote| sync::plan_changes(batch.essays, candidates, remote, false)).await
+}
+
+/// 持有同步锁读取数据库状态、生成计划并在一个事务中写入
+async fn write(
+    store: &dyn EssayStore,
+    make_plan: impl FnOnce(&HashMap<String, (String, Option<String>)>) -> SyncPlan,
+) -> Result<()> {
+    let mut session = store.begin_sync().await?;
+    let res = async {
+        let remote = store.query_essays_sync_state().await?;
+        let plan = make_plan(&remote);
+        for change in &plan.changes {
+            println!("->> {:<12} - {}", change.action().label(), change_summary(change));
+        }
+        session.apply(&plan, now()).await.map_err(|err| anyhow::anyhow!(err.message))
+    }
+    .await;
+    session.release().await?;
+    res
+}
+
+/// 给没有 eid 的文章生成 eid 写回文件，写回失败的文章不参与这次同步
+fn assign_eids(batch: &mut LoadedBatch) {
+    let logged = batch.errors.len();
+    for (path, eid) in utils::assign_eids(batch, false) {
+        println!("->> {:<12} - {path} -> {eid}", "EID");
+    }
+    for error in &batch.errors[logged..] {
+        println!("->> {:<12} - {}", "ERROR", error.message);
+    }
+}
+
+/// 打印读取文章时的 warning 和错误
+fn log_batch(batch: &LoadedBatch) {
+    for warning in &batch.warnings {
+        println!("->> {:<12} - {warning}", "WARN");
+    }
+    for error in &batch.errors {
+        println!("->> {:<12} - {}", "ERROR", error.message);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use notify_debouncer_mini::DebouncedEventKind;
+
+    use super::*;
+
+    #[test]
+    fn removed_directories_match_known_files_by_component() {
+        let dir = std::env::temp_dir().join(format!("rusite-watch-{}", push_server::now()));
+        let known: HashMap<_, _> = ["a/x.md", "a/sub/y.md", "ab/z.md"]
+            .iter()
+            .map(|path| (dir.join(path).to_string_lossy().to_string(), String::new()))
+            .collect();
+        let event = DebouncedEvent { path: dir.join("a"), kind: DebouncedEventKind::Any };
+        let expected: Vec<_> = ["a/sub/y.md", "a/x.md"]
+            .iter()
+            .map(|path| dir.join(path).to_string_lossy().to_string())
+            .collect();
+        assert_eq!(affected_paths(&[event], &known), expected);
+    }
+}
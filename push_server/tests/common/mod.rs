@@ -0,0 +1,39 @@
+//! 集成测试共用的辅助函数，每个测试文件只用到其中一部分
+#![allow(dead_code)]
+
+use std::collections::HashMap;
+
+use anyhow::Result;
+use push_server::{
+    data_struct::{EssayQuery, EssaySortKey, MatchMode, SortOrder},
+    dbops::store::EssayStore,
+    sync::{plan, LocalEssay},
+};
+
+/// 第一页、每页 10 篇、按标题升序，只按 `tags` 过滤
+pub fn query(tags: &[&str]) -> EssayQuery {
+    EssayQuery {
+        page: 1,
+        per_page: 10,
+        sort: EssaySortKey::Title,
+        order: SortOrder::Asc,
+        tags: tags.iter().map(|tag| tag.to_string()).collect(),
+        categories: Vec::new(),
+        mode: MatchMode::All,
+    }
+}
+
+/// 在 unix 时间戳 `current_time` 时把 `local` 同步到 `store`，允许删除，返回同步之后的状态
+pub async fn sync(
+    store: &dyn EssayStore,
+    local: Vec<LocalEssay>,
+    current_time: f64,
+) -> Result<HashMap<String, (String, Option<String>)>> {
+    let remote = store.query_essays_sync_state().await?;
+    let plan = plan(local, &remote, true, false);
+    let mut session = store.begin_sync().await?;
+    let applied = session.apply(&plan, current_time).await;
+    session.release().await?;
+    applied.map_err(|err| anyhow::anyhow!(err.message))?;
+    store.query_essays_sync_state().await
+}
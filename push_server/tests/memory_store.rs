@@ -0,0 +1,89 @@
+//! 不使用数据库，直接从 markdown 目录加载文章
+
+mod common;
+
+use std::{fs, path::Path};
+
+use anyhow::Result;
+use push_server::{
+    data_struct::MarkdownRenderer,
+    dbops::{memory::MemoryStore, store::EssayStore},
+};
+use uuid::Uuid;
+
+use common::query;
+
+fn write_essay(dir: &Path, name: &str, eid: &str, title: &str, tags: &str, body: &str) -> Result<()> {
+    let eid = if eid.is_empty() { String::new() } else { format!("eid: \"{eid}\"\n") };
+    fs::write(
+        dir.join(name),
+        format!("---\n{eid}title: \"{title}\"\ndate: \"2024-01-01 00:00:00\"\ncategories: [\"notes\"]\ntags: {tags}\nbrief: \"brief\"\n---\n{body}\n"),
+    )?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn load_and_reload_directory() -> Result<()> {
+    let dir = std::env::temp_dir().join(format!("rusite-memory-{}", Uuid::new_v4()));
+    fs::create_dir_all(dir.join("sub"))?;
+    write_essay(&dir, "a.md", "a", "Alpha", "[\"Rust\", \"db\"]", "rust sqlite storage")?;
+    write_essay(&dir.join("sub"), "b.md", "b", "Beta", "[\"DB\"]", "mysql")?;
+    // 没有 eid 和 eid 重复的文章被跳过
+    write_essay(&dir, "c.md", "", "Gamma", "[]", "no eid")?;
+    write_essay(&dir, "d.md", "a", "Delta", "[]", "duplicate")?;
+
+    let store = MemoryStore::load(dir.to_str().unwrap(), MarkdownRenderer::new()).await?;
+    let (items, total) = store.query_essay_info_page(&query(&["db"])).await?;
+    assert_eq!(total, 2);
+    assert_eq!(items.iter().map(|info| info.title.as_str()).collect::<Vec<_>>(), ["Alpha", "Beta"]);
+
+    let tags = store.query_tag_counts().await?;
+    let tags: Vec<_> = tags.iter().map(|tag| (tag.name.as_str(), tag.count)).collect();
+    assert_eq!(tags, [("db", 2), ("Rust", 1)]);
+
+    let (hits, count) = store.search_essays("sqlite", 10).await?;
+    assert_eq!(count, 1);
+    assert_eq!(hits[0].info.eid, "a");
+    assert!(store.begin_sync().await.is_err());
+
+    fs::remove_file(dir.join("sub/b.md"))?;
+    write_essay(&dir, "a.md", "a", "Alpha 2", "[]", "changed")?;
+    store.reload().await?;
+    let (essay, _) = store.query_essay("a").await?.unwrap();
+    assert_eq!(essay.title, "Alpha 2");
+    assert!(store.query_essay("b").await?.is_none());
+    assert_eq!(store.search_essays("sqlite", 10).await?.1, 0);
+
+    fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn unpublished_essays_are_hidden() -> Result<()> {
+    let dir = std::env::temp_dir().join(format!("rusite-memory-{}", Uuid::new_v4()));
+    fs::create_dir_all(&dir)?;
+    let essay = |eid: &str, extra: &str| {
+        format!("---\neid: \"{eid}\"\ntitle: \"{eid}\"\ndate: \"2024-01-01 00:00:00\"\ncategories: [\"notes\"]\ntags: [\"{eid}\"]\nbrief: \"brief\"\n{extra}---\nsecret\n")
+    };
+    fs::write(dir.join("draft.md"), essay("draft", "draft: true\n"))?;
+    fs::write(dir.join("scheduled.md"), essay("scheduled", "publish_at: \"2999-01-01 00:00:00\"\n"))?;
+    fs::write(dir.join("published.md"), essay("published", "publish_at: \"2000-1-1 0:00:00\"\n"))?;
+
+    let store = MemoryStore::load(dir.to_str().unwrap(), MarkdownRenderer::new()).await?;
+    let (items, total) = store.query_essay_info_page(&query(&[])).await?;
+    assert_eq!(total, 1);
+    assert_eq!(items[0].eid, "published");
+    assert_eq!(store.query_essay_page(&query(&[])).await?.len(), 1);
+    let tags = store.query_tag_counts().await?;
+    assert_eq!(tags.iter().map(|tag| tag.name.as_str()).collect::<Vec<_>>(), ["published"]);
+    assert_eq!(store.search_essays("secret", 10).await?.1, 1);
+    assert_eq!(store.query_essays_last_save_time().await?.len(), 1);
+
+    // 单篇文章仍然可以查到，publish_at 读取时已经统一格式
+    let (essay, _) = store.query_essay("published").await?.unwrap();
+    assert_eq!(essay.publish_at.as_deref(), Some("2000-01-01 00:00:00"));
+    assert!(store.query_essay("draft").await?.unwrap().0.draft);
+
+    fs::remove_dir_all(&dir)?;
+    Ok(())
+}
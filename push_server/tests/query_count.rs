@@ -0,0 +1,161 @@
+//! 文章列表的查询次数不随文章数增长。语句数由 sqlx 在 `sqlx::query` 目标上记录的事件统计。
+//! SQLite 的测试在 `sqlite::memory:` 中进行；MySQL 的测试需要 `DATABASE_URL` 指向一个可以建库的 MySQL 服务器，
+//! 在一个临时数据库中进行，结束后删除，不会读写 `DATABASE_URL` 中原有的数据：
+//! `cargo test -p push_server --test query_count -- --ignored`
+
+mod common;
+
+use std::{
+    str::FromStr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Once,
+    },
+};
+
+use anyhow::Result;
+use dotenv::dotenv;
+use push_server::{
+    data_struct::{Essay, EssayQuery, EssaySortKey, SortOrder},
+    dbops::store::{connect_url, EssayStore, MySqlStore},
+    sync::LocalEssay,
+    DATABASE_URL,
+};
+use sqlx::mysql::{MySqlConnectOptions, MySqlPoolOptions};
+use tokio::sync::Mutex;
+use tracing::{span, Event, Metadata, Subscriber};
+use uuid::Uuid;
+
+use common::query;
+
+/// 执行过的语句数，所有测试共用
+static STATEMENTS: AtomicUsize = AtomicUsize::new(0);
+/// 同一时间只有一个测试在统计
+static COUNTING: Mutex<()> = Mutex::const_new(());
+
+/// 只统计 sqlx 执行语句时记录的事件。SQLite 的语句在单独的线程上执行，所以要设置为全局的 subscriber
+struct StatementCounter;
+
+impl Subscriber for StatementCounter {
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        metadata.target() == "sqlx::query"
+    }
+
+    fn new_span(&self, _: &span::Attributes<'_>) -> span::Id {
+        span::Id::from_u64(1)
+    }
+
+    fn record(&self, _: &span::Id, _: &span::Record<'_>) {}
+
+    fn record_follows_from(&self, _: &span::Id, _: &span::Id) {}
+
+    fn event(&self, event: &Event<'_>) {
+        if self.enabled(event.metadata()) {
+            STATEMENTS.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    fn enter(&self, _: &span::Id) {}
+
+    fn exit(&self, _: &span::Id) {}
+}
+
+fn install_counter() {
+    static INSTALL: Once = Once::new();
+    INSTALL.call_once(|| tracing::subscriber::set_global_default(StatementCounter).expect("can't install subscriber"));
+}
+
+/// 执行 `f` 期间的语句数
+async fn count_queries<F, Fut>(f: F) -> Result<usize>
+where
+    F: Fn() -> Fut,
+    Fut: std::future::Future<Output = Result<()>>,
+{
+    // 先执行一次，排除第一次 prepare 语句的影响
+    f().await?;
+    let before = STATEMENTS.load(Ordering::SeqCst);
+    f().await?;
+    Ok(STATEMENTS.load(Ordering::SeqCst) - before)
+}
+
+fn local(i: usize) -> LocalEssay {
+    let eid = Uuid::new_v4().to_string();
+    let essay = Essay::new(
+        eid.clone(),
+        format!("query count {i}"),
+        String::from("2024-01-01 00:00:00"),
+        vec![String::from("query-count-category")],
+        vec![String::from("query-count-a"), format!("query-count-{i}")],
+        String::from("brief"),
+        String::from("<p>content</p>"),
+    );
+    LocalEssay { path: format!("{eid}.md"), essay, content_hash: String::new() }
+}
+
+/// 依次写入 1, 10, 50 篇文章，返回每次列表查询的语句数
+async fn listing_query_counts(store: &dyn EssayStore) -> Result<Vec<(usize, usize)>> {
+    install_counter();
+    let _counting = COUNTING.lock().await;
+    store.migrate().await?;
+    let query = EssayQuery { per_page: 100, sort: EssaySortKey::Date, order: SortOrder::Desc, ..query(&[]) };
+
+    let mut essays = Vec::new();
+    let mut counts = Vec::new();
+    for n in [1, 10, 50] {
+        while essays.len() < n {
+            essays.push(local(essays.len()));
+        }
+        common::sync(store, essays.clone(), 1.0).await?;
+
+        let query = &query;
+        let page = count_queries(|| async move { store.query_essay_info_page(query).await.map(drop) }).await?;
+        let full_page = count_queries(|| async move { store.query_essay_page(query).await.map(drop) }).await?;
+        println!("{n:>3} essays: query_essay_info_page {page}, query_essay_page {full_page}");
+        counts.push((page, full_page));
+    }
+    Ok(counts)
+}
+
+fn assert_flat(counts: &[(usize, usize)]) {
+    assert!(counts.iter().all(|&(page, full_page)| page > 0 && full_page > 0), "no statements counted: {counts:?}");
+    assert!(counts.windows(2).all(|w| w[0] == w[1]), "query count grows with essay count: {counts:?}");
+}
+
+#[tokio::test]
+async fn sqlite_essay_listing_query_count_is_flat() -> Result<()> {
+    let store = connect_url("sqlite::memory:").await?;
+    assert_flat(&listing_query_counts(store.as_ref()).await?);
+    Ok(())
+}
+
+#[tokio::test]
+#[ignore = "needs a MySQL server in DATABASE_URL"]
+async fn essay_listing_query_count_is_flat() -> Result<()> {
+    dotenv().ok();
+    // 库名只包含 uuid 的十六进制字符，拼接进 SQL 是安全的
+    let database = format!("rusite_query_count_{}", Uuid::new_v4().simple());
+    let server = MySqlPoolOptions::new()
+        .max_connections(1)
+        .connect(&DATABASE_URL)
+        .await?;
+    sqlx::query(&format!("CREATE DATABASE `{database}`"))
+        .execute(&server)
+        .await?;
+
+    let options = MySqlConnectOptions::from_str(&DATABASE_URL)?.database(&database);
+    let res = match MySqlPoolOptions::new().max_connections(1).connect_with(options).await {
+        Ok(pool) => {
+            let res = listing_query_counts(&MySqlStore::new(pool.clone())).await;
+            pool.close().await;
+            res
+        },
+        Err(err) => Err(err.into()),
+    };
+
+    // 无论成功与否都删除临时数据库
+    sqlx::query(&format!("DROP DATABASE `{database}`"))
+        .execute(&server)
+        .await?;
+    assert_flat(&res?);
+    Ok(())
+}
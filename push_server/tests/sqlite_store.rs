@@ -0,0 +1,181 @@
+//! 用 `sqlite::memory:` 走一遍同步和查询，不需要外部数据库
+
+mod common;
+
+use std::collections::HashMap;
+
+use anyhow::Result;
+use push_server::{
+    data_struct::Essay,
+    dbops::store::{connect_url, EssayStore},
+    now,
+    sync::LocalEssay,
+};
+
+use common::query;
+
+fn local(eid: &str, title: &str, tags: &[&str], content: &str) -> LocalEssay {
+    let essay = Essay::new(
+        eid.to_string(),
+        title.to_string(),
+        String::from("2024-01-01 00:00:00"),
+        vec![String::from("notes")],
+        tags.iter().map(|tag| tag.to_string()).collect(),
+        String::from("brief"),
+        content.to_string(),
+    );
+    LocalEssay {
+        path: format!("{eid}.md"),
+        content_hash: format!("{title}{content}"),
+        essay,
+    }
+}
+
+async fn sync(store: &dyn EssayStore, local: Vec<LocalEssay>) -> Result<HashMap<String, (String, Option<String>)>> {
+    common::sync(store, local, 1.0).await
+}
+
+#[tokio::test]
+async fn sync_and_query_in_memory() -> Result<()> {
+    let store = connect_url("sqlite::memory:").await?;
+    assert!(!store.migrate().await?.is_empty());
+    assert!(store.migrate().await?.is_empty());
+
+    let state = sync(
+        store.as_ref(),
+        vec![
+            local("a", "Alpha", &["Rust", "rust", "db"], "<p>rust sqlite storage</p>"),
+            local("b", "Beta", &["db"], "<p>mysql</p>"),
+        ],
+    )
+    .await?;
+    assert_eq!(state.len(), 2);
+
+    // 大小写不同的 tag 是同一个
+    let tags = store.query_tag_counts().await?;
+    let tags: Vec<_> = tags.iter().map(|tag| (tag.name.as_str(), tag.count)).collect();
+    assert_eq!(tags, [("db", 2), ("Rust", 1)]);
+
+    let (items, total) = store.query_essay_info_page(&query(&["db"])).await?;
+    assert_eq!(total, 2);
+    assert_eq!(items.iter().map(|info| info.title.as_str()).collect::<Vec<_>>(), ["Alpha", "Beta"]);
+    assert_eq!(items[0].tags, ["Rust", "db"]);
+    assert_eq!(items[0].categories, ["notes"]);
+    assert_eq!(items[0].date, "2024-01-01 00:00:00");
+
+    let (hits, count) = store.search_essays("sqlite", 10).await?;
+    assert_eq!(count, 1);
+    assert_eq!(hits[0].info.eid, "a");
+
+    // 修改 a，删除 b
+    sync(store.as_ref(), vec![local("a", "Alpha 2", &["db"], "<p>changed</p>")]).await?;
+    let (essay, last_save_time) = store.query_essay("a").await?.unwrap();
+    assert_eq!(essay.title, "Alpha 2");
+    assert_eq!(essay.tags, ["db"]);
+    assert_eq!(last_save_time, 1.0);
+    assert!(store.query_essay("b").await?.is_none());
+    assert_eq!(store.search_essays("sqlite", 10).await?.1, 0);
+    assert_eq!(store.query_essay_page(&query(&[])).await?.len(), 1);
+    Ok(())
+}
+
+#[tokio::test]
+async fn unpublished_essays_are_hidden() -> Result<()> {
+    let store = connect_url("sqlite::memory:").await?;
+    store.migrate().await?;
+
+    let mut draft = local("draft", "Draft", &["hidden"], "<p>secret</p>");
+    draft.essay.draft = true;
+    let mut scheduled = local("scheduled", "Scheduled", &["hidden"], "<p>secret</p>");
+    scheduled.essay.publish_at = Some(String::from("2999-01-01 00:00:00"));
+    let mut published = local("published", "Published", &["shown"], "<p>secret</p>");
+    published.essay.publish_at = Some(String::from("2000-01-01 00:00:00"));
+    sync(store.as_ref(), vec![draft, scheduled, published]).await?;
+
+    let (items, total) = store.query_essay_info_page(&query(&[])).await?;
+    assert_eq!(total, 1);
+    assert_eq!(items[0].eid, "published");
+    assert_eq!(store.query_essay_page(&query(&[])).await?.len(), 1);
+    let tags = store.query_tag_counts().await?;
+    assert_eq!(tags.iter().map(|tag| tag.name.as_str()).collect::<Vec<_>>(), ["shown"]);
+    assert_eq!(store.search_essays("secret", 10).await?.1, 1);
+    assert_eq!(store.query_essays_last_save_time().await?.len(), 1);
+
+    // 单篇文章仍然可以查到，由调用者决定是否公开
+    let (essay, _) = store.query_essay("scheduled").await?.unwrap();
+    assert_eq!(essay.publish_at.as_deref(), Some("2999-01-01 00:00:00"));
+    assert!(!essay.is_published("2024-01-01 00:00:00"));
+    assert!(essay.is_published("2999-01-01 00:00:00"));
+    assert!(store.query_essay("draft").await?.unwrap().0.draft);
+    Ok(())
+}
+
+#[tokio::test]
+async fn updates_are_archived_as_revisions() -> Result<()> {
+    let store = connect_url("sqlite::memory:").await?;
+    store.migrate().await?;
+
+    sync(store.as_ref(), vec![local("a", "First", &["db", "Rust"], "<p>one</p>")]).await?;
+    assert!(store.query_revisions("a").await?.is_empty());
+    sync(store.as_ref(), vec![local("a", "Second", &["db"], "<p>two</p>")]).await?;
+    sync(store.as_ref(), vec![local("a", "Third", &[], "<p>three</p>")]).await?;
+
+    let revisions = store.query_revisions("a").await?;
+    assert_eq!(revisions.iter().map(|info| (info.revision, info.title.as_str())).collect::<Vec<_>>(), [(2, "Second"), (1, "First")]);
+    let first = store.query_revision("a", 1).await?.unwrap();
+    assert_eq!(first.tags, ["Rust", "db"]);
+    assert_eq!(first.categories, ["notes"]);
+    assert_eq!(first.content, "<p>one</p>");
+    assert_eq!(first.date, "2024-01-01 00:00:00");
+    assert!(store.query_revision("a", 3).await?.is_none());
+
+    // 只改了源文件中不影响历史版本字段的部分时不存历史版本
+    let mut scheduled = local("a", "Third", &[], "<p>three</p>");
+    scheduled.essay.publish_at = Some(String::from("2000-01-01 00:00:00"));
+    scheduled.content_hash = String::from("changed source");
+    sync(store.as_ref(), vec![scheduled]).await?;
+    assert_eq!(store.query_revisions("a").await?.len(), 2);
+
+    // 删除文章时一起删除历史版本
+    sync(store.as_ref(), Vec::new()).await?;
+    assert!(store.query_revisions("a").await?.is_empty());
+    Ok(())
+}
+
+#[tokio::test]
+async fn sync_lock_rejects_concurrent_sync() -> Result<()> {
+    let store = connect_url("sqlite::memory:").await?;
+    store.migrate().await?;
+
+    let session = store.begin_sync().await?;
+    assert!(store.begin_sync().await.is_err());
+    session.release().await?;
+    store.begin_sync().await?.release().await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn revisions_keep_publish_state() -> Result<()> {
+    let store = connect_url("sqlite::memory:").await?;
+    store.migrate().await?;
+
+    let mut draft = local("a", "Draft", &[], "<p>draft</p>");
+    draft.essay.draft = true;
+    sync(store.as_ref(), vec![draft]).await?;
+    let mut scheduled = local("a", "Scheduled", &[], "<p>scheduled</p>");
+    scheduled.essay.publish_at = Some(String::from("2999-01-01 00:00:00"));
+    common::sync(store.as_ref(), vec![scheduled], now()).await?;
+    let mut published = local("a", "Published", &[], "<p>published</p>");
+    published.essay.publish_at = Some(String::from("2000-01-01 00:00:00"));
+    common::sync(store.as_ref(), vec![published], now()).await?;
+    // 版本在被替换时是否已经到了发布时间
+    common::sync(store.as_ref(), vec![local("a", "Current", &[], "<p>current</p>")], now()).await?;
+
+    let revisions = store.query_revisions("a").await?;
+    let published: Vec<_> = revisions.iter().map(|info| (info.title.as_str(), info.was_published())).collect();
+    assert_eq!(published, [("Published", true), ("Scheduled", false), ("Draft", false)]);
+    let scheduled = store.query_revision("a", 2).await?.unwrap();
+    assert_eq!(scheduled.info.publish_at.as_deref(), Some("2999-01-01 00:00:00"));
+    assert!(store.query_revision("a", 1).await?.unwrap().info.draft);
+    Ok(())
+}
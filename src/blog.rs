@@ -0,0 +1,270 @@
+//! Blog API types
+//! (query parameters and response envelopes for `/api/blog`)
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use push_server::data_struct::{Essay, EssayQuery, EssaySortKey, MatchMode, SearchHit, SortOrder};
+use serde::{Deserialize, Serialize};
+
+pub const DEFAULT_PER_PAGE: u32 = 10;
+pub const MAX_PER_PAGE: u32 = 100;
+
+// region:    --- List Params
+/// `GET /api/blog?page=&per_page=&sort=&order=&tag=&category=&match=`
+///
+/// `tag` 和 `category` 可以用逗号分隔多个值，`match` 为 `all` (默认) 或 `any`。
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct ListParams {
+	pub page: Option<u32>,
+	pub per_page: Option<u32>,
+	pub sort: Option<EssaySortKey>,
+	pub order: Option<SortOrder>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub tag: Option<String>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub category: Option<String>,
+	#[serde(rename = "match", skip_serializing_if = "Option::is_none")]
+	pub mode: Option<MatchMode>,
+}
+
+impl ListParams {
+	pub fn to_query(&self) -> EssayQuery {
+		EssayQuery {
+			page: self.page.unwrap_or(1).max(1),
+			per_page: self
+				.per_page
+				.unwrap_or(DEFAULT_PER_PAGE)
+				.clamp(1, MAX_PER_PAGE),
+			sort: self.sort.unwrap_or_default(),
+			order: self.order.unwrap_or_default(),
+			tags: split_terms(self.tag.as_deref()),
+			categories: split_terms(self.category.as_deref()),
+			mode: self.mode.unwrap_or_default(),
+		}
+	}
+
+	/// 由 `query` 还原出查询参数，用于生成翻页链接
+	fn from_query(query: &EssayQuery, page: u32) -> Self {
+		let join = |terms: &[String]| (!terms.is_empty()).then(|| terms.join(","));
+		Self {
+			page: Some(page),
+			per_page: Some(query.per_page),
+			sort: Some(query.sort),
+			order: Some(query.order),
+			tag: join(&query.tags),
+			category: join(&query.categories),
+			mode: (!query.tags.is_empty() || !query.categories.is_empty()).then_some(query.mode),
+		}
+	}
+}
+
+fn split_terms(terms: Option<&str>) -> Vec<String> {
+	terms
+		.unwrap_or_default()
+		.split(',')
+		.map(str::trim)
+		.filter(|term| !term.is_empty())
+		.map(String::from)
+		.collect()
+}
+// endregion: --- List Params
+
+// region:    --- Page Envelope
+#[derive(Debug, Serialize)]
+pub struct Page<T> {
+	pub items: Vec<T>,
+	pub total: u64,
+	pub page: u32,
+	pub per_page: u32,
+	pub total_pages: u32,
+	pub next: Option<String>,
+	pub prev: Option<String>,
+}
+
+impl<T> Page<T> {
+	/// `base` 为列表的路径，用来生成 `next` / `prev` 链接
+	pub fn new(items: Vec<T>, total: u64, query: &EssayQuery, base: &str) -> Self {
+		let total_pages = total.div_ceil(query.per_page as u64) as u32;
+		let link = |page: u32| {
+			let params = ListParams::from_query(query, page);
+			format!("{base}?{}", serde_urlencoded::to_string(params).unwrap_or_default())
+		};
+		let next = (query.page < total_pages).then(|| link(query.page + 1));
+		let prev = (query.page > 1).then(|| link((query.page - 1).min(total_pages.max(1))));
+
+		Self {
+			items,
+			total,
+			page: query.page,
+			per_page: query.per_page,
+			total_pages,
+			next,
+			prev,
+		}
+	}
+}
+// endregion: --- Page Envelope
+
+// region:    --- Search
+/// `GET /api/search?q=&limit=`
+#[derive(Debug, Deserialize)]
+pub struct SearchParams {
+	pub q: String,
+	pub limit: Option<u32>,
+}
+
+impl SearchParams {
+	pub fn limit(&self) -> u32 {
+		self.limit.unwrap_or(DEFAULT_PER_PAGE).clamp(1, MAX_PER_PAGE)
+	}
+}
+
+#[derive(Debug, Serialize)]
+pub struct SearchResults {
+	pub q: String,
+	pub total: u64,
+	pub items: Vec<SearchHit>,
+}
+// endregion: --- Search
+
+// region:    --- Preview
+/// `GET /api/blog/:eid/preview?token=`，用 `PREVIEW_TOKEN` 查看草稿和还没有到发布时间的文章
+#[derive(Debug, Deserialize)]
+pub struct PreviewParams {
+	pub token: String,
+}
+
+impl PreviewParams {
+	/// token 是否和 `expected` 相同，比较的时间和相同前缀的长度无关
+	pub fn matches(&self, expected: &str) -> bool {
+		let (token, expected) = (self.token.as_bytes(), expected.as_bytes());
+		token.len() == expected.len()
+			&& token.iter().zip(expected).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+	}
+}
+// endregion: --- Preview
+
+// region:    --- Essay Document
+/// `GET /api/blog/:eid` 返回的完整文章
+#[derive(Debug, Serialize)]
+pub struct EssayDocument {
+	#[serde(flatten)]
+	pub essay: Essay,
+	/// 最后一次推送的时间 (RFC 3339, UTC)
+	pub updated: String,
+}
+
+impl EssayDocument {
+	pub fn new(essay: Essay, last_save_time: f64) -> Self {
+		Self {
+			essay,
+			updated: timestamp_to_rfc3339(last_save_time),
+		}
+	}
+}
+
+/// 把 unix 时间戳 (秒) 转为 UTC 时间
+pub fn timestamp_to_datetime(timestamp: f64) -> DateTime<Utc> {
+	DateTime::from_timestamp(timestamp.trunc() as i64, (timestamp.fract() * 1e9) as u32)
+		.unwrap_or_default()
+}
+
+/// 把 unix 时间戳 (秒) 转为 RFC 3339 字符串
+pub fn timestamp_to_rfc3339(timestamp: f64) -> String {
+	timestamp_to_datetime(timestamp).to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+// endregion: --- Essay Document
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn params(token: &str) -> PreviewParams {
+		PreviewParams { token: token.to_string() }
+	}
+
+	#[test]
+	fn preview_token_matches() {
+		assert!(params("secret").matches("secret"));
+		assert!(!params("secreT").matches("secret"));
+		assert!(!params("secret2").matches("secret"));
+		assert!(!params("secre").matches("secret"));
+		assert!(!params("").matches("secret"));
+	}
+
+	fn list_params(query: &str) -> ListParams {
+		serde_urlencoded::from_str(query).unwrap()
+	}
+
+	/// 第 `page` 页的 (next, prev)
+	fn links(total: u64, page: u32) -> (Option<String>, Option<String>) {
+		let query = list_params(&format!("page={page}&per_page=10")).to_query();
+		let page: Page<()> = Page::new(Vec::new(), total, &query, "/api/blog");
+		(page.next, page.prev)
+	}
+
+	fn link(page: u32) -> Option<String> {
+		Some(format!("/api/blog?page={page}&per_page=10&sort=date&order=desc"))
+	}
+
+	#[test]
+	fn page_links() {
+		assert_eq!(links(25, 1), (link(2), None));
+		assert_eq!(links(25, 2), (link(3), link(1)));
+		assert_eq!(links(25, 3), (None, link(2)));
+		// 超出最后一页时 prev 指向最后一页
+		assert_eq!(links(25, 7), (None, link(3)));
+		assert_eq!(links(20, 2), (None, link(1)));
+	}
+
+	#[test]
+	fn empty_pages() {
+		let query = ListParams::default().to_query();
+		let page: Page<()> = Page::new(Vec::new(), 0, &query, "/api/blog");
+		assert_eq!((page.total_pages, page.page, page.next, page.prev), (0, 1, None, None));
+		assert_eq!(links(0, 3), (None, link(1)));
+	}
+
+	#[test]
+	fn list_params_are_clamped() {
+		let query = list_params("page=0&per_page=0").to_query();
+		assert_eq!((query.page, query.per_page), (1, 1));
+		let query = list_params("per_page=1000").to_query();
+		assert_eq!((query.page, query.per_page), (1, MAX_PER_PAGE));
+		let query = ListParams::default().to_query();
+		assert_eq!((query.per_page, query.sort, query.order), (DEFAULT_PER_PAGE, EssaySortKey::Date, SortOrder::Desc));
+		assert!(serde_urlencoded::from_str::<ListParams>("page=-1").is_err());
+	}
+
+	#[test]
+	fn links_keep_sort_and_order() {
+		let query = list_params("page=2&per_page=5&sort=last_save_time&order=asc").to_query();
+		let page: Page<()> = Page::new(Vec::new(), 20, &query, "/api/blog");
+		let next = page.next.unwrap();
+		assert_eq!(next, "/api/blog?page=3&per_page=5&sort=last_save_time&order=asc");
+		let next = list_params(next.split_once('?').unwrap().1).to_query();
+		assert_eq!((next.page, next.per_page, next.sort, next.order), (3, 5, EssaySortKey::LastSaveTime, SortOrder::Asc));
+		assert!(next.tags.is_empty() && next.categories.is_empty());
+	}
+
+	#[test]
+	fn links_keep_filters() {
+		let query = list_params("page=2&per_page=5&tag=%E4%B8%AD%E6%96%87,+rust+,&category=a%26b&match=any").to_query();
+		assert_eq!(query.tags, ["中文", "rust"]);
+		assert_eq!(query.categories, ["a&b"]);
+		let page: Page<()> = Page::new(Vec::new(), 20, &query, "/api/tags");
+		assert_eq!(
+			page.prev.unwrap(),
+			"/api/tags?page=1&per_page=5&sort=date&order=desc&tag=%E4%B8%AD%E6%96%87%2Crust&category=a%26b&match=any"
+		);
+		let next = page.next.unwrap();
+		let next = list_params(next.split_once('?').unwrap().1).to_query();
+		assert_eq!((next.page, next.tags, next.categories, next.mode), (3, query.tags, query.categories, MatchMode::Any));
+	}
+
+	#[test]
+	fn match_is_kept_only_with_filters() {
+		let query = list_params("match=any").to_query();
+		let page: Page<()> = Page::new(Vec::new(), 20, &query, "/api/blog");
+		assert_eq!(page.next.unwrap(), link(2).unwrap());
+	}
+}
@@ -0,0 +1,45 @@
+//! 站点配置，从环境变量 (.env) 读取
+
+use lazy_static::lazy_static;
+use std::env;
+
+lazy_static! {
+    /// 站点的公开地址，不带结尾的 `/`
+    pub static ref SITE_URL: String = env::var("SITE_URL")
+        .map(|url| url.trim_end_matches('/').to_string())
+        .unwrap_or_else(|_| String::from("http://localhost:8216"));
+    pub static ref SITE_TITLE: String = env::var("SITE_TITLE").unwrap_or_else(|_| String::from("rusite"));
+    pub static ref SITE_DESCRIPTION: String = env::var("SITE_DESCRIPTION").unwrap_or_default();
+    pub static ref SITE_AUTHOR: String = env::var("SITE_AUTHOR").unwrap_or_default();
+    /// 自定义 robots.txt 的文件路径，未设置时使用默认内容
+    pub static ref ROBOTS_TXT: Option<String> = env::var("ROBOTS_TXT").ok();
+    /// 预览还没有发布的文章用的 token，未设置时不能预览
+    pub static ref PREVIEW_TOKEN: Option<String> = env::var("PREVIEW_TOKEN").ok().filter(|token| !token.is_empty());
+    /// markdown 文章目录。设置后不连接数据库，直接从这个目录加载文章并在文件变化时重新加载
+    pub static ref ESSAYS_SOURCE: Option<String> = env::var("ESSAYS_SOURCE").ok().filter(|source| !source.is_empty());
+    /// push_server 的 config.json 路径，`ESSAYS_SOURCE` 模式下按其中的 `renderer` 渲染文章并写出高亮 css。
+    /// 未设置时使用默认的渲染配置
+    pub static ref RENDERER_CONFIG: Option<String> = env::var("RENDERER_CONFIG").ok().filter(|path| !path.is_empty());
+}
+
+/// 文章在前端的页面地址
+pub fn essay_url(eid: &str) -> String {
+    format!("{}/blog/{eid}", *SITE_URL)
+}
+
+/// tag / category 列表页在前端的地址，`kind` 为 `tags` 或 `categories`
+pub fn taxonomy_url(kind: &str, name: &str) -> String {
+    format!("{}/{kind}/{}", *SITE_URL, encode_path_segment(name))
+}
+
+/// 对 url 路径中的一段做百分号编码
+pub fn encode_path_segment(segment: &str) -> String {
+    let mut res = String::with_capacity(segment.len());
+    for byte in segment.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => res.push(byte as char),
+            _ => res.push_str(&format!("%{byte:02X}")),
+        }
+    }
+    res
+}
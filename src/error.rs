@@ -1,20 +1,172 @@
-use axum::{http::StatusCode, response::IntoResponse};
+use axum::{
+    extract::rejection::{PathRejection, QueryRejection},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use serde::Serialize;
 
 pub type Result<T> = core::result::Result<T, Error>;
 
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum Error {
     LoginFail,
 
+    // -- Request errors
+    BadRequest { reason: String },
+    EssayNotFound { eid: String },
+    RevisionNotFound { eid: String, revision: u32 },
+    FileNotFound { file: String },
+
+    // -- Storage errors
+    DatabaseUnavailable { detail: String },
+    Internal { detail: String },
+
     // -- Model error
     TicketDeleteFailIdNotFound { id: u64 },
 }
 
 impl IntoResponse for Error {
-    fn into_response(self) -> axum::response::Response {
-        println!("->> {:<12} - {self:?}", "INFO_RES");
+    fn into_response(self) -> Response {
+        println!("->> {:<12} - {self:?}", "INTO_RES");
+
+        // 占位响应，返回给客户端的 body 由 main_response_mapper 根据 extensions 中的 Error 生成
+        let mut response = StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        response.extensions_mut().insert(self);
+        response
+    }
+}
 
-        (StatusCode::INTERNAL_SERVER_ERROR, "UNHANDLE_CLIENT_ERROR").into_response()
+impl Error {
+    /// 服务端错误到客户端错误的映射，内部细节不会返回给客户端
+    pub fn client_status_and_error(&self) -> (StatusCode, ClientError) {
+        match self {
+            Self::LoginFail => (StatusCode::FORBIDDEN, ClientError::LoginFail),
+
+            Self::BadRequest { .. } => (StatusCode::BAD_REQUEST, ClientError::InvalidParams),
+
+            Self::EssayNotFound { .. }
+            | Self::RevisionNotFound { .. }
+            | Self::FileNotFound { .. }
+            | Self::TicketDeleteFailIdNotFound { .. } => {
+                (StatusCode::NOT_FOUND, ClientError::NotFound)
+            },
+
+            Self::DatabaseUnavailable { .. } => {
+                (StatusCode::SERVICE_UNAVAILABLE, ClientError::ServiceUnavailable)
+            },
+
+            Self::Internal { .. } => {
+                (StatusCode::INTERNAL_SERVER_ERROR, ClientError::ServiceError)
+            },
+        }
     }
-}
\ No newline at end of file
+
+    /// 可以安全返回给客户端的说明
+    pub fn client_message(&self) -> String {
+        match self {
+            Self::BadRequest { reason } => reason.clone(),
+            Self::EssayNotFound { eid } => format!("essay {eid} not found"),
+            Self::RevisionNotFound { eid, revision } => format!("revision {revision} of essay {eid} not found"),
+            Self::FileNotFound { file } => format!("{file} not found"),
+            _ => self.client_status_and_error().1.message().to_string(),
+        }
+    }
+}
+
+impl From<anyhow::Error> for Error {
+    fn from(err: anyhow::Error) -> Self {
+        let detail = format!("{err:?}");
+        match err.downcast_ref::<sqlx::Error>() {
+            Some(
+                sqlx::Error::PoolTimedOut
+                | sqlx::Error::PoolClosed
+                | sqlx::Error::Io(_)
+                | sqlx::Error::Tls(_),
+            ) => Self::DatabaseUnavailable { detail },
+            _ => Self::Internal { detail },
+        }
+    }
+}
+
+impl From<QueryRejection> for Error {
+    fn from(rejection: QueryRejection) -> Self {
+        Self::BadRequest { reason: rejection.body_text() }
+    }
+}
+
+impl From<PathRejection> for Error {
+    fn from(rejection: PathRejection) -> Self {
+        Self::BadRequest { reason: rejection.body_text() }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ClientError {
+    LoginFail,
+    NotFound,
+    InvalidParams,
+    ServiceUnavailable,
+    ServiceError,
+}
+
+impl ClientError {
+    pub fn message(&self) -> &'static str {
+        match self {
+            Self::LoginFail => "login failed",
+            Self::NotFound => "resource not found",
+            Self::InvalidParams => "invalid request parameters",
+            Self::ServiceUnavailable => "service temporarily unavailable",
+            Self::ServiceError => "internal server error",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn client_status_codes() {
+        let cases = [
+            (Error::LoginFail, StatusCode::FORBIDDEN),
+            (Error::BadRequest { reason: String::new() }, StatusCode::BAD_REQUEST),
+            (Error::EssayNotFound { eid: String::new() }, StatusCode::NOT_FOUND),
+            (Error::RevisionNotFound { eid: String::new(), revision: 1 }, StatusCode::NOT_FOUND),
+            (Error::FileNotFound { file: String::new() }, StatusCode::NOT_FOUND),
+            (Error::TicketDeleteFailIdNotFound { id: 1 }, StatusCode::NOT_FOUND),
+            (Error::DatabaseUnavailable { detail: String::new() }, StatusCode::SERVICE_UNAVAILABLE),
+            (Error::Internal { detail: String::new() }, StatusCode::INTERNAL_SERVER_ERROR),
+        ];
+        for (err, status) in cases {
+            assert_eq!(err.client_status_and_error().0, status, "{err:?}");
+        }
+    }
+
+    #[test]
+    fn client_messages_hide_internal_details() {
+        let err = Error::RevisionNotFound { eid: String::from("e1"), revision: 3 };
+        assert_eq!(err.client_message(), "revision 3 of essay e1 not found");
+        assert_eq!(Error::BadRequest { reason: String::from("bad page") }.client_message(), "bad page");
+        let err = Error::Internal { detail: String::from("secret path") };
+        assert_eq!(err.client_message(), "internal server error");
+        let err = Error::DatabaseUnavailable { detail: String::from("secret host") };
+        assert_eq!(err.client_message(), "service temporarily unavailable");
+    }
+
+    #[test]
+    fn database_connection_errors_are_unavailable() {
+        let err = Error::from(anyhow::Error::new(sqlx::Error::PoolTimedOut).context("query essays"));
+        assert!(matches!(err, Error::DatabaseUnavailable { .. }), "{err:?}");
+        let err = Error::from(anyhow::Error::new(sqlx::Error::RowNotFound));
+        assert!(matches!(err, Error::Internal { .. }), "{err:?}");
+        assert!(matches!(Error::from(anyhow::anyhow!("boom")), Error::Internal { .. }));
+    }
+
+    #[test]
+    fn client_error_codes_are_screaming_snake_case() {
+        assert_eq!(serde_json::to_value(ClientError::NotFound).unwrap(), "NOT_FOUND");
+        assert_eq!(serde_json::to_value(ClientError::ServiceUnavailable).unwrap(), "SERVICE_UNAVAILABLE");
+    }
+}
@@ -0,0 +1,362 @@
+//! RSS 2.0, Atom and JSON Feed generation
+
+use chrono::{DateTime, Local, NaiveDateTime, SecondsFormat, TimeZone, Utc};
+use push_server::{
+    data_struct::{Essay, EssayQuery, EssaySortKey, MatchMode, SortOrder},
+    time::DATE_FORMAT,
+};
+use serde_json::json;
+
+use crate::{
+    blog::{timestamp_to_datetime, timestamp_to_rfc3339},
+    config,
+};
+
+/// 每个 feed 中最多包含的文章数
+pub const FEED_LIMIT: u32 = 20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FeedKind {
+    Rss,
+    Atom,
+    Json,
+}
+
+impl FeedKind {
+    /// 根据文件名 (`feed.xml`, `atom.xml`, `feed.json`) 得到 feed 类型
+    pub fn from_file_name(name: &str) -> Option<Self> {
+        match name {
+            "feed.xml" => Some(Self::Rss),
+            "atom.xml" => Some(Self::Atom),
+            "feed.json" => Some(Self::Json),
+            _ => None,
+        }
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            Self::Rss => "application/rss+xml; charset=utf-8",
+            Self::Atom => "application/atom+xml; charset=utf-8",
+            Self::Json => "application/feed+json; charset=utf-8",
+        }
+    }
+}
+
+/// 最新的 `FEED_LIMIT` 篇文章，可以按 tag / category 过滤
+pub fn feed_query(tags: Vec<String>, categories: Vec<String>) -> EssayQuery {
+    EssayQuery {
+        page: 1,
+        per_page: FEED_LIMIT,
+        sort: EssaySortKey::Date,
+        order: SortOrder::Desc,
+        tags,
+        categories,
+        mode: MatchMode::All,
+    }
+}
+
+/// feed 的频道信息
+pub struct FeedChannel {
+    pub title: String,
+    pub description: String,
+    /// 频道对应的 html 页面
+    pub link: String,
+    /// feed 自身的地址
+    pub feed_url: String,
+}
+
+impl FeedChannel {
+    /// 全站 feed，`path` 为 feed 的路径，例如 `/feed.xml`
+    pub fn site(path: &str) -> Self {
+        Self {
+            title: config::SITE_TITLE.clone(),
+            description: config::SITE_DESCRIPTION.clone(),
+            link: config::SITE_URL.clone(),
+            feed_url: format!("{}{path}", *config::SITE_URL),
+        }
+    }
+
+    /// tag / category 的 feed，`kind` 为 `tags` 或 `categories`
+    pub fn taxonomy(kind: &str, name: &str, file: &str) -> Self {
+        Self {
+            title: format!("{} - {name}", *config::SITE_TITLE),
+            description: config::SITE_DESCRIPTION.clone(),
+            link: config::taxonomy_url(kind, name),
+            feed_url: format!(
+                "{}/api/{kind}/{}/{file}",
+                *config::SITE_URL,
+                config::encode_path_segment(name)
+            ),
+        }
+    }
+}
+
+/// 生成 feed，`essays` 中为 (文章, 最后保存时间)
+pub fn render(kind: FeedKind, channel: &FeedChannel, essays: &[(Essay, f64)]) -> String {
+    match kind {
+        FeedKind::Rss => render_rss(channel, essays),
+        FeedKind::Atom => render_atom(channel, essays),
+        FeedKind::Json => render_json(channel, essays),
+    }
+}
+
+fn render_rss(channel: &FeedChannel, essays: &[(Essay, f64)]) -> String {
+    let mut xml = String::from(r#"<?xml version="1.0" encoding="UTF-8"?>"#);
+    xml.push_str(r#"<rss version="2.0" xmlns:atom="http://www.w3.org/2005/Atom" xmlns:content="http://purl.org/rss/1.0/modules/content/">"#);
+    xml.push_str("<channel>");
+    push_element(&mut xml, "title", &channel.title);
+    push_element(&mut xml, "link", &channel.link);
+    push_element(&mut xml, "description", &channel.description);
+    xml.push_str(&format!(
+        r#"<atom:link href="{}" rel="self" type="application/rss+xml"/>"#,
+        escape_xml(&channel.feed_url)
+    ));
+    if let Some((_, last_save_time)) = essays.iter().max_by(|a, b| a.1.total_cmp(&b.1)) {
+        push_element(&mut xml, "lastBuildDate", &timestamp_to_datetime(*last_save_time).to_rfc2822());
+    }
+    for (essay, _) in essays {
+        let url = config::essay_url(&essay.eid);
+        xml.push_str("<item>");
+        push_element(&mut xml, "title", &essay.title);
+        push_element(&mut xml, "link", &url);
+        xml.push_str(&format!(r#"<guid isPermaLink="false">{}</guid>"#, escape_xml(&essay.eid)));
+        if let Some(date) = essay_date(&essay.date, &Local) {
+            push_element(&mut xml, "pubDate", &date.to_rfc2822());
+        }
+        for category in essay.categories.iter().chain(&essay.tags) {
+            push_element(&mut xml, "category", category);
+        }
+        push_element(&mut xml, "description", &essay.brief);
+        xml.push_str("<content:encoded>");
+        xml.push_str(&cdata(&essay.content));
+        xml.push_str("</content:encoded>");
+        xml.push_str("</item>");
+    }
+    xml.push_str("</channel></rss>");
+    xml
+}
+
+fn render_atom(channel: &FeedChannel, essays: &[(Essay, f64)]) -> String {
+    let updated = essays
+        .iter()
+        .map(|(_, last_save_time)| *last_save_time)
+        .max_by(f64::total_cmp)
+        .unwrap_or_default();
+
+    let mut xml = String::from(r#"<?xml version="1.0" encoding="UTF-8"?>"#);
+    xml.push_str(r#"<feed xmlns="http://www.w3.org/2005/Atom">"#);
+    push_element(&mut xml, "id", &channel.feed_url);
+    push_element(&mut xml, "title", &channel.title);
+    if !channel.description.is_empty() {
+        push_element(&mut xml, "subtitle", &channel.description);
+    }
+    push_element(&mut xml, "updated", &timestamp_to_rfc3339(updated));
+    xml.push_str(&format!(r#"<link href="{}"/>"#, escape_xml(&channel.link)));
+    xml.push_str(&format!(r#"<link href="{}" rel="self"/>"#, escape_xml(&channel.feed_url)));
+    if !config::SITE_AUTHOR.is_empty() {
+        xml.push_str("<author>");
+        push_element(&mut xml, "name", &config::SITE_AUTHOR);
+        xml.push_str("</author>");
+    }
+    for (essay, last_save_time) in essays {
+        let url = config::essay_url(&essay.eid);
+        xml.push_str("<entry>");
+        push_element(&mut xml, "id", &format!("urn:uuid:{}", essay.eid));
+        push_element(&mut xml, "title", &essay.title);
+        xml.push_str(&format!(r#"<link href="{}"/>"#, escape_xml(&url)));
+        if let Some(date) = essay_date(&essay.date, &Local) {
+            push_element(&mut xml, "published", &date.to_rfc3339_opts(SecondsFormat::Secs, true));
+        }
+        push_element(&mut xml, "updated", &timestamp_to_rfc3339(*last_save_time));
+        for category in essay.categories.iter().chain(&essay.tags) {
+            xml.push_str(&format!(r#"<category term="{}"/>"#, escape_xml(category)));
+        }
+        push_element(&mut xml, "summary", &essay.brief);
+        xml.push_str(r#"<content type="html">"#);
+        xml.push_str(&escape_xml(&essay.content));
+        xml.push_str("</content>");
+        xml.push_str("</entry>");
+    }
+    xml.push_str("</feed>");
+    xml
+}
+
+fn render_json(channel: &FeedChannel, essays: &[(Essay, f64)]) -> String {
+    let items: Vec<_> = essays
+        .iter()
+        .map(|(essay, last_save_time)| {
+            json!({
+                "id": essay.eid,
+                "url": config::essay_url(&essay.eid),
+                "title": essay.title,
+                "content_html": essay.content,
+                "summary": essay.brief,
+                "date_published": essay_date(&essay.date, &Local)
+                    .map(|date| date.to_rfc3339_opts(SecondsFormat::Secs, true)),
+                "date_modified": timestamp_to_rfc3339(*last_save_time),
+                "tags": essay.categories.iter().chain(&essay.tags).collect::<Vec<_>>(),
+            })
+        })
+        .collect();
+    let mut feed = json!({
+        "version": "https://jsonfeed.org/version/1.1",
+        "title": channel.title,
+        "home_page_url": channel.link,
+        "feed_url": channel.feed_url,
+        "items": items,
+    });
+    if !channel.description.is_empty() {
+        feed["description"] = json!(channel.description);
+    }
+    if !config::SITE_AUTHOR.is_empty() {
+        feed["authors"] = json!([{ "name": *config::SITE_AUTHOR }]);
+    }
+    feed.to_string()
+}
+
+/// 文章的 date 字段格式为 [`DATE_FORMAT`]
+pub fn parse_essay_date(date: &str) -> Option<NaiveDateTime> {
+    NaiveDateTime::parse_from_str(date, DATE_FORMAT).ok()
+}
+
+/// 文章的 date 是服务器的本地时间，`tz` 为服务器所在的时区，返回对应的 UTC 时间。
+/// 夏令时切换时重复的时间取较早的一个，跳过的时间返回 `None`
+fn essay_date<Tz: TimeZone>(date: &str, tz: &Tz) -> Option<DateTime<Utc>> {
+    let date = tz.from_local_datetime(&parse_essay_date(date)?).earliest()?;
+    Some(date.with_timezone(&Utc))
+}
+
+pub fn escape_xml(text: &str) -> String {
+    let mut res = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '<' => res.push_str("&lt;"),
+            '>' => res.push_str("&gt;"),
+            '&' => res.push_str("&amp;"),
+            '"' => res.push_str("&quot;"),
+            '\'' => res.push_str("&apos;"),
+            _ => res.push(c),
+        }
+    }
+    res
+}
+
+fn push_element(xml: &mut String, name: &str, text: &str) {
+    xml.push_str(&format!("<{name}>{}</{name}>", escape_xml(text)));
+}
+
+/// 用 CDATA 包裹 html，内容中的 `]]>` 需要拆开
+fn cdata(text: &str) -> String {
+    format!("<![CDATA[{}]]>", text.replace("]]>", "]]]]><![CDATA[>"))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::FixedOffset;
+
+    use super::*;
+
+    fn channel() -> FeedChannel {
+        FeedChannel {
+            title: String::from("Tom & Jerry's <blog>"),
+            description: String::from("notes"),
+            link: String::from("https://example.com"),
+            feed_url: String::from("https://example.com/feed.xml?a=1&b=2"),
+        }
+    }
+
+    fn essays() -> Vec<(Essay, f64)> {
+        let essay = Essay::new(
+            String::from("e1"),
+            String::from("A < B & \"C\""),
+            String::from("2024-01-05 09:30:00"),
+            vec![String::from("rust")],
+            vec![String::from("c&c")],
+            String::from("brief <b>"),
+            String::from("<p>x]]>y</p>"),
+        );
+        vec![(essay, 1704447000.0)]
+    }
+
+    #[test]
+    fn escape_xml_escapes_markup() {
+        assert_eq!(escape_xml(r#"<a href="x">Tom's & co</a>"#), "&lt;a href=&quot;x&quot;&gt;Tom&apos;s &amp; co&lt;/a&gt;");
+        assert_eq!(cdata("a]]>b"), "<![CDATA[a]]]]><![CDATA[>b]]>");
+    }
+
+    #[test]
+    fn rss_escapes_text_and_wraps_content() {
+        let xml = render(FeedKind::Rss, &channel(), &essays());
+        assert!(xml.starts_with(r#"<?xml version="1.0" encoding="UTF-8"?><rss version="2.0""#));
+        assert!(xml.contains("<title>Tom &amp; Jerry&apos;s &lt;blog&gt;</title>"));
+        assert!(xml.contains(r#"<atom:link href="https://example.com/feed.xml?a=1&amp;b=2" rel="self""#));
+        assert!(xml.contains("<lastBuildDate>Fri, 5 Jan 2024 09:30:00 +0000</lastBuildDate>"));
+        assert!(xml.contains("<title>A &lt; B &amp; &quot;C&quot;</title>"));
+        assert!(xml.contains(&format!("<link>{}</link>", config::essay_url("e1"))));
+        assert!(xml.contains(r#"<guid isPermaLink="false">e1</guid>"#));
+        let published = essay_date("2024-01-05 09:30:00", &Local).unwrap();
+        assert!(xml.contains(&format!("<pubDate>{}</pubDate>", published.to_rfc2822())));
+        assert!(xml.contains("<category>rust</category><category>c&amp;c</category>"));
+        assert!(xml.contains("<description>brief &lt;b&gt;</description>"));
+        assert!(xml.contains("<content:encoded><![CDATA[<p>x]]]]><![CDATA[>y</p>]]></content:encoded>"));
+        assert!(xml.ends_with("</item></channel></rss>"));
+    }
+
+    #[test]
+    fn atom_escapes_text_and_content() {
+        let xml = render(FeedKind::Atom, &channel(), &essays());
+        assert!(xml.contains(r#"<feed xmlns="http://www.w3.org/2005/Atom"><id>https://example.com/feed.xml?a=1&amp;b=2</id>"#));
+        assert!(xml.contains("<subtitle>notes</subtitle><updated>2024-01-05T09:30:00Z</updated>"));
+        assert!(xml.contains(r#"<link href="https://example.com/feed.xml?a=1&amp;b=2" rel="self"/>"#));
+        assert!(xml.contains("<entry><id>urn:uuid:e1</id><title>A &lt; B &amp; &quot;C&quot;</title>"));
+        let published = essay_date("2024-01-05 09:30:00", &Local).unwrap().to_rfc3339_opts(SecondsFormat::Secs, true);
+        assert!(xml.contains(&format!("<published>{published}</published><updated>2024-01-05T09:30:00Z</updated>")));
+        assert!(xml.contains(r#"<category term="rust"/><category term="c&amp;c"/>"#));
+        assert!(xml.contains(r#"<content type="html">&lt;p&gt;x]]&gt;y&lt;/p&gt;</content>"#));
+        assert!(xml.ends_with("</entry></feed>"));
+    }
+
+    #[test]
+    fn atom_without_essays_is_valid() {
+        let xml = render(FeedKind::Atom, &channel(), &[]);
+        assert!(xml.contains("<updated>1970-01-01T00:00:00Z</updated>"));
+        assert!(!xml.contains("<entry>"));
+    }
+
+    #[test]
+    fn json_feed_items() {
+        let feed: serde_json::Value = serde_json::from_str(&render(FeedKind::Json, &channel(), &essays())).unwrap();
+        assert_eq!(feed["version"], "https://jsonfeed.org/version/1.1");
+        assert_eq!(feed["title"], "Tom & Jerry's <blog>");
+        assert_eq!(feed["feed_url"], "https://example.com/feed.xml?a=1&b=2");
+        assert_eq!(feed["description"], "notes");
+        let item = &feed["items"][0];
+        assert_eq!(item["id"], "e1");
+        assert_eq!(item["url"], config::essay_url("e1"));
+        assert_eq!(item["content_html"], "<p>x]]>y</p>");
+        let published = essay_date("2024-01-05 09:30:00", &Local).unwrap().to_rfc3339_opts(SecondsFormat::Secs, true);
+        assert_eq!(item["date_published"], published);
+        assert_eq!(item["date_modified"], "2024-01-05T09:30:00Z");
+        assert_eq!(item["tags"], serde_json::json!(["rust", "c&c"]));
+    }
+
+    #[test]
+    fn essay_dates_are_server_local_time() {
+        let east8 = FixedOffset::east_opt(8 * 3600).unwrap();
+        let date = essay_date("2024-01-05 09:30:00", &east8).unwrap();
+        assert_eq!(date.to_rfc3339_opts(SecondsFormat::Secs, true), "2024-01-05T01:30:00Z");
+        assert_eq!(date.to_rfc2822(), "Fri, 5 Jan 2024 01:30:00 +0000");
+        let west5 = FixedOffset::west_opt(5 * 3600).unwrap();
+        let date = essay_date("2024-01-05 22:00:00", &west5).unwrap();
+        assert_eq!(date.to_rfc3339_opts(SecondsFormat::Secs, true), "2024-01-06T03:00:00Z");
+        assert_eq!(essay_date("2024-01-05", &Utc), None);
+    }
+
+    #[test]
+    fn feed_kind_from_file_name() {
+        assert_eq!(FeedKind::from_file_name("feed.xml"), Some(FeedKind::Rss));
+        assert_eq!(FeedKind::from_file_name("atom.xml"), Some(FeedKind::Atom));
+        assert_eq!(FeedKind::from_file_name("feed.json"), Some(FeedKind::Json));
+        assert_eq!(FeedKind::from_file_name("rss.xml"), None);
+    }
+}
@@ -1,6 +1,11 @@
 pub mod fallback;
 pub mod error;
 pub mod model;
+pub mod blog;
+pub mod config;
+pub mod feed;
+pub mod revision;
+pub mod sitemap;
 
 #[cfg(test)]
 mod test {
@@ -1,41 +1,82 @@
 use axum::{
-    extract::{Path, State}, http::Method, middleware, response::{IntoResponse, Response}, routing::get, Json, Router
+    extract::{rejection::{PathRejection, QueryRejection}, Path, Query, State}, http::{header, HeaderName, Method}, middleware, response::{IntoResponse, Response}, routing::get, Json, Router
 };
-use rusite_server::fallback::routers_static;
-use sqlx::{MySql, Pool};
+use push_server::data_struct::{Essay, EssayInfo, EssayQuery, Revision, RevisionInfo, TermCount};
+use rusite_server::{
+    blog::{EssayDocument, ListParams, Page, PreviewParams, SearchParams, SearchResults},
+    fallback::routers_static,
+    config,
+    feed::{self, FeedChannel, FeedKind},
+    revision::{DiffParams, RevisionDiff, RevisionDocument, RevisionSummary},
+    sitemap,
+};
+use serde_json::json;
+use std::sync::Arc;
 use tower_cookies::CookieManagerLayer;
 pub use rusite_server::error::{Error, Result};
 
 use tower_http::cors::{CorsLayer, any};
+use uuid::Uuid;
 
-use push_server::dbops::{
-    tables_ops::{ query_essay_content, query_essay_info },
-    utils::build_pool
+use push_server::{
+    data_struct::{MarkdownRenderer, RendererConfig},
+    dbops::{memory::MemoryStore, store::{self, EssayStore}},
+    now_date,
 };
 
 
 #[derive(Clone)]
 struct AppState {
-    db: Pool<MySql>,
+    db: Arc<dyn EssayStore>,
+    /// 预览用的 token，为 `None` 时不能预览
+    preview_token: Option<String>,
 }
 
 impl AppState {
-    fn new(db: Pool<MySql>) -> Self {
-        Self {db}
+    fn new(db: Arc<dyn EssayStore>, preview_token: Option<String>) -> Self {
+        Self {db, preview_token}
     }
 }
 
+/// 设置了 `ESSAYS_SOURCE` 时按 `RENDERER_CONFIG` 中的渲染配置从 markdown 目录加载文章，
+/// 否则连接 `DATABASE_URL` 并执行迁移
+async fn connect() -> Arc<dyn EssayStore> {
+    if let Some(source) = config::ESSAYS_SOURCE.as_deref() {
+        let renderer_config = match config::RENDERER_CONFIG.as_deref() {
+            Some(path) => RendererConfig::from_config_file(path).expect("can't read renderer config"),
+            None => RendererConfig::default(),
+        };
+        let renderer = MarkdownRenderer::with_config(&renderer_config).expect("invalid renderer config");
+        renderer.write_highlight_css(&renderer_config).await.expect("can't write highlight css");
+        let store = MemoryStore::load(source, renderer).await.expect("can't load essays");
+        let store = Arc::new(store);
+        let watched = store.clone();
+        tokio::spawn(async move {
+            if let Err(err) = watched.watch().await {
+                println!("->> {:<12} - can't watch essays: {err:#}", "ERROR");
+            }
+        });
+        return store;
+    }
+    let store = store::connect().await.expect("can't connect database");
+    for (version, description) in store.migrate().await.expect("can't migrate database") {
+        println!("->> {:<12} - {version} {description}", "MIGRATE");
+    }
+    store
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
     
     dotenv::dotenv().ok();
 
-    let pool = build_pool().await.unwrap();
-
-    let state = AppState::new(pool);
+    let store = connect().await;
+    let state = AppState::new(store, config::PREVIEW_TOKEN.clone());
 
     let app = Router::new()
         .route("/", get(|| async { "Hello, World!" }))
+        .merge(feed_route(state.clone()))
+        .merge(sitemap_route(state.clone()))
         .nest("/api", api_route(state))
         .layer(middleware::map_response(main_response_mapper))
         .layer(CorsLayer::new()
@@ -52,37 +93,451 @@ async fn main() -> Result<()> {
 }
 
 async fn main_response_mapper(res: Response) -> Response {
-    println!("->> {:<12} - main_response_mapper\n", "RES_MAPPER");
-    res
+    println!("->> {:<12} - main_response_mapper", "RES_MAPPER");
+    let request_id = Uuid::new_v4();
+
+    // -- 如果 handler 返回了 Error，生成统一的 json 错误响应
+    let service_error = res.extensions().get::<Error>();
+    let error_response = service_error.map(|se| {
+        let (status_code, client_error) = se.client_status_and_error();
+        // 内部细节只打印在服务端
+        println!("->> {:<12} - {request_id} - {se:?}", "SERVER_ERR");
+        let client_error_body = json!({
+            "code": client_error,
+            "message": se.client_message(),
+            "request_id": request_id.to_string(),
+        });
+        (status_code, Json(client_error_body)).into_response()
+    });
+
+    println!();
+    error_response.unwrap_or(res)
 }
 
 fn api_route(state: AppState) -> Router {
     Router::new()
-        .nest("/blog", blog_route(state))
+        .nest("/blog", blog_route(state.clone()))
+        .merge(taxonomy_route(state))
 }
-    
+
+fn feed_route(state: AppState) -> Router {
+    Router::new()
+        .route("/feed.xml", get(handler_feed_rss))
+        .route("/atom.xml", get(handler_feed_atom))
+        .route("/feed.json", get(handler_feed_json))
+        .with_state(state)
+}
+
+fn sitemap_route(state: AppState) -> Router {
+    Router::new()
+        .route("/sitemap.xml", get(handler_sitemap))
+        .route("/sitemaps/:file", get(handler_sitemap_part))
+        .route("/robots.txt", get(handler_robots))
+        .with_state(state)
+}
+
+fn taxonomy_route(state: AppState) -> Router {
+    Router::new()
+        .route("/tags", get(handler_tag_list))
+        .route("/tags/:name/:file", get(handler_tag_feed))
+        .route("/categories", get(handler_category_list))
+        .route("/categories/:name/:file", get(handler_category_feed))
+        .route("/search", get(handler_search))
+        .with_state(state)
+}
+
 fn blog_route(state: AppState) -> Router {
     Router::new()
         .route("/", get(handler_blog_info_list))
-        .route("/:eid", get(handler_blog_content))
+        .route("/:eid", get(handler_blog_essay))
+        .route("/:eid/preview", get(handler_blog_preview))
+        .route("/:eid/revisions", get(handler_revision_list))
+        .route("/:eid/revisions/:revision", get(handler_revision))
+        .route("/:eid/diff", get(handler_revision_diff))
         .with_state(state)
 }
 
 async fn handler_blog_info_list(
+    params: core::result::Result<Query<ListParams>, QueryRejection>,
     State(state): State<AppState>,
-) -> impl IntoResponse {
+) -> Result<Json<Page<EssayInfo>>> {
     println!("->> {:<12} - handler_blog_info_list", "HANDLER");
-    let pool = state.db;
-    let res = query_essay_info(&pool).await.unwrap();
-    Json(res)
+    let Query(params) = params?;
+    let store = state.db;
+    let query = params.to_query();
+    let (items, total) = store.query_essay_info_page(&query).await?;
+    Ok(Json(Page::new(items, total, &query, "/api/blog")))
 }
 
-async fn handler_blog_content(
-    Path(eid): Path<String>,
+async fn handler_blog_essay(
+    path: core::result::Result<Path<String>, PathRejection>,
     State(state): State<AppState>,
-) -> impl IntoResponse {
-    println!("->> {:<12} - handler_blog_content", "HANDLER");
-    let pool = &state.db;
-    let res = query_essay_content(pool, &eid).await.unwrap();
-    res.unwrap_or(Default::default())
-}
\ No newline at end of file
+) -> Result<Json<EssayDocument>> {
+    println!("->> {:<12} - handler_blog_essay", "HANDLER");
+    let Path(eid) = path?;
+    let (essay, last_save_time) = published_essay(&state, eid).await?;
+    Ok(Json(EssayDocument::new(essay, last_save_time)))
+}
+
+/// 已经发布的文章，草稿和还没有到发布时间的文章和不存在的文章一样返回 404
+async fn published_essay(state: &AppState, eid: String) -> Result<(Essay, f64)> {
+    check_eid(&eid)?;
+    let store = &state.db;
+    let res = store.query_essay(&eid)
+        .await?
+        .filter(|(essay, _)| essay.is_published(&now_date()))
+        .ok_or(Error::EssayNotFound { eid })?;
+    Ok(res)
+}
+
+async fn handler_revision_list(
+    path: core::result::Result<Path<String>, PathRejection>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<RevisionSummary>>> {
+    println!("->> {:<12} - handler_revision_list", "HANDLER");
+    let Path(eid) = path?;
+    let (essay, _) = published_essay(&state, eid).await?;
+    let revisions = state.db.query_revisions(&essay.eid).await?;
+    Ok(Json(revisions
+        .into_iter()
+        .filter(RevisionInfo::was_published)
+        .map(RevisionSummary::from)
+        .collect()))
+}
+
+async fn handler_revision(
+    path: core::result::Result<Path<(String, u32)>, PathRejection>,
+    State(state): State<AppState>,
+) -> Result<Json<RevisionDocument>> {
+    println!("->> {:<12} - handler_revision", "HANDLER");
+    let Path((eid, revision)) = path?;
+    let (essay, _) = published_essay(&state, eid).await?;
+    let revision = query_revision(&state, &essay.eid, revision).await?;
+    Ok(Json(revision.into()))
+}
+
+async fn handler_revision_diff(
+    path: core::result::Result<Path<String>, PathRejection>,
+    params: core::result::Result<Query<DiffParams>, QueryRejection>,
+    State(state): State<AppState>,
+) -> Result<Json<RevisionDiff>> {
+    println!("->> {:<12} - handler_revision_diff", "HANDLER");
+    let Path(eid) = path?;
+    let Query(params) = params?;
+    let (essay, _) = published_essay(&state, eid).await?;
+    let from = query_revision(&state, &essay.eid, params.from).await?;
+    let diff = match params.to {
+        Some(to) => {
+            let to_revision = query_revision(&state, &essay.eid, to).await?;
+            RevisionDiff::new(essay.eid.clone(), params.from, Some(to), (&from).into(), (&to_revision).into())
+        },
+        None => RevisionDiff::new(essay.eid.clone(), params.from, None, (&from).into(), (&essay).into()),
+    };
+    Ok(Json(diff))
+}
+
+/// 公开过的历史版本，草稿和没有到发布时间就被替换的版本和不存在的版本一样返回 404
+async fn query_revision(state: &AppState, eid: &str, revision: u32) -> Result<Revision> {
+    let res = state.db.query_revision(eid, revision)
+        .await?
+        .filter(|revision| revision.info.was_published())
+        .ok_or_else(|| Error::RevisionNotFound { eid: eid.to_string(), revision })?;
+    Ok(res)
+}
+
+async fn handler_blog_preview(
+    path: core::result::Result<Path<String>, PathRejection>,
+    params: core::result::Result<Query<PreviewParams>, QueryRejection>,
+    State(state): State<AppState>,
+) -> Result<Response> {
+    println!("->> {:<12} - handler_blog_preview", "HANDLER");
+    let Path(eid) = path?;
+    check_eid(&eid)?;
+    let Query(params) = params?;
+    // token 不对时也返回 404，不暴露预览地址是否存在
+    if !state.preview_token.as_deref().is_some_and(|token| params.matches(token)) {
+        return Err(Error::EssayNotFound { eid });
+    }
+    let store = &state.db;
+    let (essay, last_save_time) = store.query_essay(&eid)
+        .await?
+        .ok_or(Error::EssayNotFound { eid })?;
+    let headers = [
+        (header::CACHE_CONTROL, "private, no-store"),
+        (HeaderName::from_static("x-robots-tag"), "noindex"),
+    ];
+    Ok((headers, Json(EssayDocument::new(essay, last_save_time))).into_response())
+}
+
+async fn handler_tag_list(
+    State(state): State<AppState>,
+) -> Result<Json<Vec<TermCount>>> {
+    println!("->> {:<12} - handler_tag_list", "HANDLER");
+    let store = &state.db;
+    let res = store.query_tag_counts().await?;
+    Ok(Json(res))
+}
+
+async fn handler_category_list(
+    State(state): State<AppState>,
+) -> Result<Json<Vec<TermCount>>> {
+    println!("->> {:<12} - handler_category_list", "HANDLER");
+    let store = &state.db;
+    let res = store.query_category_counts().await?;
+    Ok(Json(res))
+}
+
+async fn handler_search(
+    params: core::result::Result<Query<SearchParams>, QueryRejection>,
+    State(state): State<AppState>,
+) -> Result<Json<SearchResults>> {
+    println!("->> {:<12} - handler_search", "HANDLER");
+    let Query(params) = params?;
+    let store = &state.db;
+    let (items, total) = store.search_essays(&params.q, params.limit()).await?;
+    Ok(Json(SearchResults { q: params.q, total, items }))
+}
+
+async fn handler_feed_rss(
+    State(state): State<AppState>,
+) -> Result<Response> {
+    println!("->> {:<12} - handler_feed_rss", "HANDLER");
+    site_feed(&state, FeedKind::Rss, "/feed.xml").await
+}
+
+async fn handler_feed_atom(
+    State(state): State<AppState>,
+) -> Result<Response> {
+    println!("->> {:<12} - handler_feed_atom", "HANDLER");
+    site_feed(&state, FeedKind::Atom, "/atom.xml").await
+}
+
+async fn handler_feed_json(
+    State(state): State<AppState>,
+) -> Result<Response> {
+    println!("->> {:<12} - handler_feed_json", "HANDLER");
+    site_feed(&state, FeedKind::Json, "/feed.json").await
+}
+
+async fn handler_tag_feed(
+    path: core::result::Result<Path<(String, String)>, PathRejection>,
+    State(state): State<AppState>,
+) -> Result<Response> {
+    println!("->> {:<12} - handler_tag_feed", "HANDLER");
+    let Path((name, file)) = path?;
+    let kind = FeedKind::from_file_name(&file).ok_or(Error::FileNotFound { file: file.clone() })?;
+    let channel = FeedChannel::taxonomy("tags", &name, &file);
+    let query = feed::feed_query(vec![name], Vec::new());
+    render_feed(&state, kind, &channel, &query).await
+}
+
+async fn handler_category_feed(
+    path: core::result::Result<Path<(String, String)>, PathRejection>,
+    State(state): State<AppState>,
+) -> Result<Response> {
+    println!("->> {:<12} - handler_category_feed", "HANDLER");
+    let Path((name, file)) = path?;
+    let kind = FeedKind::from_file_name(&file).ok_or(Error::FileNotFound { file: file.clone() })?;
+    let channel = FeedChannel::taxonomy("categories", &name, &file);
+    let query = feed::feed_query(Vec::new(), vec![name]);
+    render_feed(&state, kind, &channel, &query).await
+}
+
+async fn site_feed(state: &AppState, kind: FeedKind, path: &str) -> Result<Response> {
+    let channel = FeedChannel::site(path);
+    let query = feed::feed_query(Vec::new(), Vec::new());
+    render_feed(state, kind, &channel, &query).await
+}
+
+async fn render_feed(
+    state: &AppState,
+    kind: FeedKind,
+    channel: &FeedChannel,
+    query: &EssayQuery,
+) -> Result<Response> {
+    let essays = state.db.query_essay_page(query).await?;
+    let body = feed::render(kind, channel, &essays);
+    Ok(([(header::CONTENT_TYPE, kind.content_type())], body).into_response())
+}
+
+async fn handler_sitemap(
+    State(state): State<AppState>,
+) -> Result<Response> {
+    println!("->> {:<12} - handler_sitemap", "HANDLER");
+    let urls = sitemap_urls(&state).await?;
+    let body = sitemap::render_sitemap(&urls);
+    Ok(([(header::CONTENT_TYPE, "application/xml; charset=utf-8")], body).into_response())
+}
+
+async fn handler_sitemap_part(
+    path: core::result::Result<Path<String>, PathRejection>,
+    State(state): State<AppState>,
+) -> Result<Response> {
+    println!("->> {:<12} - handler_sitemap_part", "HANDLER");
+    let Path(file) = path?;
+    let urls = sitemap_urls(&state).await?;
+    let body = sitemap::render_part(&urls, &file).ok_or(Error::FileNotFound { file })?;
+    Ok(([(header::CONTENT_TYPE, "application/xml; charset=utf-8")], body).into_response())
+}
+
+async fn handler_robots() -> Result<String> {
+    println!("->> {:<12} - handler_robots", "HANDLER");
+    let template = match config::ROBOTS_TXT.as_deref() {
+        Some(path) => Some(tokio::fs::read_to_string(path).await.map_err(|err| Error::Internal {
+            detail: format!("read {path}: {err}"),
+        })?),
+        None => None,
+    };
+    Ok(sitemap::render_robots(template.as_deref()))
+}
+
+async fn sitemap_urls(state: &AppState) -> Result<Vec<sitemap::SitemapUrl>> {
+    let store = &state.db;
+    let last_save_times = store.query_essays_last_save_time().await?;
+    let tags = store.query_tag_counts().await?;
+    let categories = store.query_category_counts().await?;
+    Ok(sitemap::collect_urls(&last_save_times, &tags, &categories))
+}
+
+/// eid 必须是合法的 uuid
+fn check_eid(eid: &str) -> Result<()> {
+    Uuid::parse_str(eid)
+        .map(|_| ())
+        .map_err(|_| Error::BadRequest { reason: format!("malformed eid: {eid}") })
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::StatusCode;
+    use push_server::{dbops::store::connect_url, now, sync::{plan, LocalEssay}};
+
+    use super::*;
+
+    const EID: &str = "6c0f2b6e-2d5e-4c53-9a37-1f3a0c7d5b10";
+
+    fn local(title: &str, content: &str, draft: bool) -> LocalEssay {
+        let mut essay = Essay::new(
+            EID.to_string(),
+            title.to_string(),
+            String::from("2024-01-01 00:00:00"),
+            Vec::new(),
+            vec![title.to_lowercase()],
+            String::from("brief"),
+            content.to_string(),
+        );
+        essay.draft = draft;
+        LocalEssay { path: String::from("essay.md"), content_hash: format!("{title}{content}"), essay }
+    }
+
+    /// 依次同步 `versions`，除最后一个之外都成为历史版本
+    async fn state_with(versions: Vec<LocalEssay>) -> anyhow::Result<AppState> {
+        let store = connect_url("sqlite::memory:").await?;
+        store.migrate().await?;
+        for version in versions {
+            let remote = store.query_essays_sync_state().await?;
+            let plan = plan(vec![version], &remote, true, false);
+            let mut session = store.begin_sync().await?;
+            session.apply(&plan, now()).await.map_err(|err| anyhow::anyhow!(err.message))?;
+            session.release().await?;
+        }
+        Ok(AppState::new(store, None))
+    }
+
+    async fn diff(state: &AppState, from: u32, to: Option<u32>) -> Result<RevisionDiff> {
+        let params = Ok(Query(DiffParams { from, to }));
+        let Json(diff) = handler_revision_diff(Ok(Path(EID.to_string())), params, State(state.clone())).await?;
+        Ok(diff)
+    }
+
+    #[tokio::test]
+    async fn diff_endpoint_compares_revisions_and_current() -> anyhow::Result<()> {
+        let state = state_with(vec![
+            local("First", "<p>one</p>\n", false),
+            local("Second", "<p>two</p>\n", false),
+            local("Third", "<p>three</p>\n", false),
+        ])
+        .await?;
+
+        let current = diff(&state, 1, None).await.unwrap();
+        assert_eq!(current.title.unwrap().new, "Third");
+        assert_eq!(current.tags.added, ["third"]);
+        assert!(current.content.contains("+++ current\n"));
+
+        let between = diff(&state, 1, Some(2)).await.unwrap();
+        assert_eq!(between.to, Some(2));
+        assert_eq!(between.title.unwrap().new, "Second");
+        assert!(between.content.contains("-<p>one</p>\n+<p>two</p>\n"));
+
+        assert!(matches!(diff(&state, 3, None).await, Err(Error::RevisionNotFound { revision: 3, .. })));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn diff_endpoint_hides_unpublished_revisions() -> anyhow::Result<()> {
+        let state = state_with(vec![
+            local("Draft", "<p>secret</p>\n", true),
+            local("Public", "<p>public</p>\n", false),
+        ])
+        .await?;
+
+        assert!(matches!(diff(&state, 1, None).await, Err(Error::RevisionNotFound { revision: 1, .. })));
+        let Json(revisions) = handler_revision_list(Ok(Path(EID.to_string())), State(state.clone())).await.unwrap();
+        assert!(revisions.is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn errors_are_mapped_to_json_bodies() -> anyhow::Result<()> {
+        let res = main_response_mapper(Error::EssayNotFound { eid: String::from("e1") }.into_response()).await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+        let body = axum::body::to_bytes(res.into_body(), usize::MAX).await?;
+        let body: serde_json::Value = serde_json::from_slice(&body)?;
+        assert_eq!(body["code"], "NOT_FOUND");
+        assert_eq!(body["message"], "essay e1 not found");
+        assert!(body["request_id"].as_str().is_some_and(|id| Uuid::parse_str(id).is_ok()));
+
+        let res = main_response_mapper(Error::Internal { detail: String::from("secret") }.into_response()).await;
+        assert_eq!(res.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        let body = axum::body::to_bytes(res.into_body(), usize::MAX).await?;
+        assert!(!String::from_utf8_lossy(&body).contains("secret"));
+
+        let res = main_response_mapper(StatusCode::OK.into_response()).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn bad_path_parameters_get_json_errors() -> anyhow::Result<()> {
+        let state = state_with(Vec::new()).await?;
+        let app = api_route(state).layer(middleware::map_response(main_response_mapper));
+        let request = axum::http::Request::get(format!("/blog/{EID}/revisions/abc")).body(axum::body::Body::empty())?;
+        let res = tower::ServiceExt::oneshot(app, request).await?;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+        let body = axum::body::to_bytes(res.into_body(), usize::MAX).await?;
+        let body: serde_json::Value = serde_json::from_slice(&body)?;
+        assert_eq!(body["code"], "INVALID_PARAMS");
+        assert!(body["message"].as_str().is_some_and(|message| message.contains("abc")), "{body}");
+        assert!(body["request_id"].as_str().is_some_and(|id| Uuid::parse_str(id).is_ok()));
+        Ok(())
+    }
+
+    async fn preview(state: &AppState, token: &str) -> Result<Response> {
+        let params = Ok(Query(PreviewParams { token: token.to_string() }));
+        handler_blog_preview(Ok(Path(EID.to_string())), params, State(state.clone())).await
+    }
+
+    #[tokio::test]
+    async fn preview_needs_the_token() -> anyhow::Result<()> {
+        let mut state = state_with(vec![local("Draft", "<p>secret</p>\n", true)]).await?;
+        assert!(matches!(preview(&state, "").await, Err(Error::EssayNotFound { .. })));
+        state.preview_token = Some(String::from("preview-secret"));
+
+        assert!(matches!(published_essay(&state, EID.to_string()).await, Err(Error::EssayNotFound { .. })));
+        assert!(matches!(preview(&state, "wrong").await, Err(Error::EssayNotFound { .. })));
+        assert!(matches!(preview(&state, "").await, Err(Error::EssayNotFound { .. })));
+        let res = preview(&state, "preview-secret").await.unwrap();
+        assert_eq!(res.status(), 200);
+        assert_eq!(res.headers()[header::CACHE_CONTROL], "private, no-store");
+        Ok(())
+    }
+}
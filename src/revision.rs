@@ -0,0 +1,219 @@
+//! 文章的历史版本和版本之间的差异
+//! (`/api/blog/:eid/revisions` 和 `/api/blog/:eid/diff`)
+
+use push_server::data_struct::{Essay, Revision, RevisionInfo};
+use serde::{Deserialize, Serialize};
+use similar::TextDiff;
+
+use crate::blog::timestamp_to_rfc3339;
+
+/// unified diff 中变化前后保留的上下文行数
+const DIFF_CONTEXT: usize = 3;
+
+/// `GET /api/blog/:eid/revisions` 中的一项
+#[derive(Debug, Serialize)]
+pub struct RevisionSummary {
+    pub revision: u32,
+    pub title: String,
+    /// 这个版本推送的时间 (RFC 3339, UTC)
+    pub saved: String,
+    /// 这个版本被新版本替换的时间 (RFC 3339, UTC)
+    pub archived: String,
+}
+
+impl From<RevisionInfo> for RevisionSummary {
+    fn from(info: RevisionInfo) -> Self {
+        Self {
+            revision: info.revision,
+            title: info.title,
+            saved: timestamp_to_rfc3339(info.saved_at),
+            archived: timestamp_to_rfc3339(info.archived_at),
+        }
+    }
+}
+
+/// `GET /api/blog/:eid/revisions/:revision` 返回的历史版本
+#[derive(Debug, Serialize)]
+pub struct RevisionDocument {
+    pub eid: String,
+    #[serde(flatten)]
+    pub summary: RevisionSummary,
+    pub date: String,
+    pub brief: String,
+    pub categories: Vec<String>,
+    pub tags: Vec<String>,
+    pub content: String,
+}
+
+impl From<Revision> for RevisionDocument {
+    fn from(revision: Revision) -> Self {
+        Self {
+            eid: revision.info.eid.clone(),
+            summary: revision.info.into(),
+            date: revision.date,
+            brief: revision.brief,
+            categories: revision.categories,
+            tags: revision.tags,
+            content: revision.content,
+        }
+    }
+}
+
+/// `GET /api/blog/:eid/diff?from=&to=`，省略 `to` 时和当前版本比较
+#[derive(Debug, Deserialize)]
+pub struct DiffParams {
+    pub from: u32,
+    pub to: Option<u32>,
+}
+
+/// 参与比较的一个版本，可以是历史版本也可以是当前的文章
+pub struct Version<'a> {
+    pub title: &'a str,
+    pub date: &'a str,
+    pub brief: &'a str,
+    pub categories: &'a [String],
+    pub tags: &'a [String],
+    pub content: &'a str,
+}
+
+impl<'a> From<&'a Revision> for Version<'a> {
+    fn from(revision: &'a Revision) -> Self {
+        Self {
+            title: &revision.info.title,
+            date: &revision.date,
+            brief: &revision.brief,
+            categories: &revision.categories,
+            tags: &revision.tags,
+            content: &revision.content,
+        }
+    }
+}
+
+impl<'a> From<&'a Essay> for Version<'a> {
+    fn from(essay: &'a Essay) -> Self {
+        Self {
+            title: &essay.title,
+            date: &essay.date,
+            brief: &essay.brief,
+            categories: &essay.categories,
+            tags: &essay.tags,
+            content: &essay.content,
+        }
+    }
+}
+
+/// 字段变化前后的值
+#[derive(Debug, Serialize)]
+pub struct FieldChange {
+    pub old: String,
+    pub new: String,
+}
+
+/// tags 或 categories 的变化
+#[derive(Debug, Default, Serialize)]
+pub struct ListChange {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+}
+
+/// 两个版本之间的差异，没有变化的字段为 `null`
+#[derive(Debug, Serialize)]
+pub struct RevisionDiff {
+    pub eid: String,
+    pub from: u32,
+    /// `null` 表示当前版本
+    pub to: Option<u32>,
+    pub title: Option<FieldChange>,
+    pub date: Option<FieldChange>,
+    pub brief: Option<FieldChange>,
+    pub categories: ListChange,
+    pub tags: ListChange,
+    /// 内容 (html) 按行比较的 unified diff，没有变化时为空字符串
+    pub content: String,
+}
+
+impl RevisionDiff {
+    pub fn new(eid: String, from: u32, to: Option<u32>, old: Version, new: Version) -> Self {
+        let to_label = to.map_or_else(|| String::from("current"), |to| format!("revision {to}"));
+        let content = if old.content == new.content {
+            String::new()
+        } else {
+            TextDiff::from_lines(old.content, new.content)
+                .unified_diff()
+                .context_radius(DIFF_CONTEXT)
+                .header(&format!("revision {from}"), &to_label)
+                .to_string()
+        };
+        Self {
+            eid,
+            from,
+            to,
+            title: field_change(old.title, new.title),
+            date: field_change(old.date, new.date),
+            brief: field_change(old.brief, new.brief),
+            categories: list_change(old.categories, new.categories),
+            tags: list_change(old.tags, new.tags),
+            content,
+        }
+    }
+}
+
+fn field_change(old: &str, new: &str) -> Option<FieldChange> {
+    (old != new).then(|| FieldChange { old: old.to_string(), new: new.to_string() })
+}
+
+fn list_change(old: &[String], new: &[String]) -> ListChange {
+    ListChange {
+        added: new.iter().filter(|name| !old.contains(name)).cloned().collect(),
+        removed: old.iter().filter(|name| !new.contains(name)).cloned().collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    fn version<'a>(title: &'a str, tags: &'a [String], content: &'a str) -> Version<'a> {
+        Version { title, date: "2024-01-01 00:00:00", brief: "brief", categories: &[], tags, content }
+    }
+
+    #[test]
+    fn list_change_ignores_order() {
+        let change = list_change(&names(&["rust", "db", "web"]), &names(&["web", "rust", "cli"]));
+        assert_eq!(change.added, ["cli"]);
+        assert_eq!(change.removed, ["db"]);
+        let change = list_change(&names(&["a", "b"]), &names(&["b", "a"]));
+        assert!(change.added.is_empty() && change.removed.is_empty());
+    }
+
+    #[test]
+    fn unchanged_fields_are_empty() {
+        let tags = names(&["rust"]);
+        let diff = RevisionDiff::new(String::from("eid"), 1, None, version("A", &tags, "<p>a</p>\n"), version("A", &tags, "<p>a</p>\n"));
+        assert!(diff.title.is_none() && diff.date.is_none() && diff.brief.is_none());
+        assert!(diff.tags.added.is_empty() && diff.tags.removed.is_empty());
+        assert_eq!(diff.content, "");
+    }
+
+    #[test]
+    fn changed_fields_and_content_diff() {
+        let (old_tags, new_tags) = (names(&["rust"]), names(&["rust", "db"]));
+        let old = version("Old", &old_tags, "<p>one</p>\n<p>two</p>\n");
+        let new = version("New", &new_tags, "<p>one</p>\n<p>three</p>\n");
+        let diff = RevisionDiff::new(String::from("eid"), 2, Some(3), old, new);
+
+        let title = diff.title.unwrap();
+        assert_eq!((title.old.as_str(), title.new.as_str()), ("Old", "New"));
+        assert_eq!(diff.tags.added, ["db"]);
+        assert!(diff.content.starts_with("--- revision 2\n+++ revision 3\n"));
+        assert!(diff.content.contains("-<p>two</p>\n+<p>three</p>\n"));
+        assert!(diff.content.contains(" <p>one</p>\n"));
+
+        let diff = RevisionDiff::new(String::from("eid"), 2, None, version("A", &[], "a\n"), version("A", &[], "b\n"));
+        assert!(diff.content.starts_with("--- revision 2\n+++ current\n"));
+    }
+}
@@ -0,0 +1,192 @@
+//! sitemap.xml and robots.txt generation
+
+use std::collections::HashMap;
+
+use push_server::data_struct::TermCount;
+
+use crate::{blog::timestamp_to_datetime, config, feed::escape_xml};
+
+/// 单个 sitemap 文件最多包含的 url 数
+pub const MAX_URLS_PER_SITEMAP: usize = 50_000;
+
+pub struct SitemapUrl {
+    pub loc: String,
+    /// W3C datetime
+    pub lastmod: Option<String>,
+}
+
+/// 首页、所有文章以及 tag / category 列表页的 url
+pub fn collect_urls(
+    last_save_times: &HashMap<String, f64>,
+    tags: &[TermCount],
+    categories: &[TermCount],
+) -> Vec<SitemapUrl> {
+    let mut urls = vec![SitemapUrl {
+        loc: format!("{}/", *config::SITE_URL),
+        lastmod: last_save_times
+            .values()
+            .copied()
+            .max_by(f64::total_cmp)
+            .map(w3c_datetime),
+    }];
+
+    let mut essays: Vec<_> = last_save_times.iter().collect();
+    essays.sort_by(|a, b| a.0.cmp(b.0));
+    urls.extend(essays.into_iter().map(|(eid, last_save_time)| SitemapUrl {
+        loc: config::essay_url(eid),
+        lastmod: Some(w3c_datetime(*last_save_time)),
+    }));
+
+    let terms = tags
+        .iter()
+        .map(|tag| ("tags", tag))
+        .chain(categories.iter().map(|category| ("categories", category)));
+    urls.extend(
+        terms
+            .filter(|(_, term)| term.count > 0)
+            .map(|(kind, term)| SitemapUrl {
+                loc: config::taxonomy_url(kind, &term.name),
+                lastmod: None,
+            }),
+    );
+    urls
+}
+
+/// `/sitemap.xml`：url 不超过 [`MAX_URLS_PER_SITEMAP`] 时直接是 `<urlset>`，
+/// 否则是指向各个分片的 `<sitemapindex>`
+pub fn render_sitemap(urls: &[SitemapUrl]) -> String {
+    match urls.len() > MAX_URLS_PER_SITEMAP {
+        true => render_index(urls.len().div_ceil(MAX_URLS_PER_SITEMAP)),
+        false => render_urlset(urls),
+    }
+}
+
+/// `/sitemaps/:file` 对应的分片，序号不合法或超出范围时为 `None`
+pub fn render_part(urls: &[SitemapUrl], file: &str) -> Option<String> {
+    let part = parse_part(file).and_then(|n| urls.chunks(MAX_URLS_PER_SITEMAP).nth(n - 1))?;
+    Some(render_urlset(part))
+}
+
+/// `<urlset>`
+pub fn render_urlset(urls: &[SitemapUrl]) -> String {
+    let mut xml = String::from(r#"<?xml version="1.0" encoding="UTF-8"?>"#);
+    xml.push_str(r#"<urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">"#);
+    for url in urls {
+        xml.push_str("<url>");
+        xml.push_str(&format!("<loc>{}</loc>", escape_xml(&url.loc)));
+        if let Some(lastmod) = &url.lastmod {
+            xml.push_str(&format!("<lastmod>{lastmod}</lastmod>"));
+        }
+        xml.push_str("</url>");
+    }
+    xml.push_str("</urlset>");
+    xml
+}
+
+/// `<sitemapindex>`，指向 `/sitemaps/1.xml` ... `/sitemaps/{count}.xml`
+pub fn render_index(count: usize) -> String {
+    let mut xml = String::from(r#"<?xml version="1.0" encoding="UTF-8"?>"#);
+    xml.push_str(r#"<sitemapindex xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">"#);
+    for n in 1..=count {
+        xml.push_str(&format!(
+            "<sitemap><loc>{}</loc></sitemap>",
+            escape_xml(&format!("{}/sitemaps/{n}.xml", *config::SITE_URL))
+        ));
+    }
+    xml.push_str("</sitemapindex>");
+    xml
+}
+
+/// 解析 `/sitemaps/:file` 中的文件名，得到从 1 开始的序号
+pub fn parse_part(file: &str) -> Option<usize> {
+    file.strip_suffix(".xml")?
+        .parse()
+        .ok()
+        .filter(|n| *n > 0)
+}
+
+/// robots.txt，`template` 为自定义的内容，缺省时允许所有爬虫。
+/// 总会带上指向 sitemap 的 `Sitemap:` 行
+pub fn render_robots(template: Option<&str>) -> String {
+    let mut robots = template
+        .map(String::from)
+        .unwrap_or_else(|| String::from("User-agent: *\nAllow: /\n"));
+    if !robots.lines().any(|line| line.to_ascii_lowercase().starts_with("sitemap:")) {
+        if !robots.ends_with('\n') {
+            robots.push('\n');
+        }
+        robots.push_str(&format!("\nSitemap: {}/sitemap.xml\n", *config::SITE_URL));
+    }
+    robots
+}
+
+fn w3c_datetime(timestamp: f64) -> String {
+    timestamp_to_datetime(timestamp).format("%Y-%m-%dT%H:%M:%SZ").to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn urls(n: usize) -> Vec<SitemapUrl> {
+        (0..n)
+            .map(|i| SitemapUrl { loc: format!("https://example.com/blog/{i}"), lastmod: None })
+            .collect()
+    }
+
+    fn locs(xml: &str) -> Vec<&str> {
+        xml.split("<loc>").skip(1).filter_map(|s| s.split_once("</loc>")).map(|(loc, _)| loc).collect()
+    }
+
+    #[test]
+    fn small_sitemaps_are_a_single_urlset() {
+        let xml = render_sitemap(&urls(MAX_URLS_PER_SITEMAP));
+        assert!(xml.contains("<urlset"));
+        assert_eq!(locs(&xml).len(), MAX_URLS_PER_SITEMAP);
+    }
+
+    #[test]
+    fn large_sitemaps_are_split_into_numbered_parts() {
+        let urls = urls(2 * MAX_URLS_PER_SITEMAP + 1);
+        let index = render_sitemap(&urls);
+        assert!(index.contains("<sitemapindex"));
+        let expected: Vec<_> = (1..=3).map(|n| format!("{}/sitemaps/{n}.xml", *config::SITE_URL)).collect();
+        assert_eq!(locs(&index), expected);
+
+        let first = render_part(&urls, "1.xml").unwrap();
+        let first = locs(&first);
+        assert_eq!(first.len(), MAX_URLS_PER_SITEMAP);
+        assert_eq!(first[0], "https://example.com/blog/0");
+        let last = render_part(&urls, "3.xml").unwrap();
+        assert_eq!(locs(&last), [format!("https://example.com/blog/{}", 2 * MAX_URLS_PER_SITEMAP)]);
+        assert_eq!(render_part(&urls, "4.xml"), None);
+        assert_eq!(render_part(&urls, "0.xml"), None);
+        assert_eq!(render_part(&urls, "1.txt"), None);
+    }
+
+    #[test]
+    fn collect_urls_skips_empty_terms() {
+        let last_save_times = HashMap::from([(String::from("b"), 1704447000.0), (String::from("a"), 1.0)]);
+        let tags = [TermCount { name: String::from("c&c"), count: 1 }, TermCount { name: String::from("old"), count: 0 }];
+        let urls = collect_urls(&last_save_times, &tags, &[]);
+        let found: Vec<_> = urls.iter().map(|url| (url.loc.clone(), url.lastmod.clone())).collect();
+        assert_eq!(
+            found,
+            [
+                (format!("{}/", *config::SITE_URL), Some(String::from("2024-01-05T09:30:00Z"))),
+                (config::essay_url("a"), Some(String::from("1970-01-01T00:00:01Z"))),
+                (config::essay_url("b"), Some(String::from("2024-01-05T09:30:00Z"))),
+                (config::taxonomy_url("tags", "c&c"), None),
+            ]
+        );
+        assert!(render_urlset(&urls).contains("/tags/c%26c</loc>"));
+    }
+
+    #[test]
+    fn robots_adds_a_sitemap_line_once() {
+        let robots = render_robots(None);
+        assert!(robots.starts_with("User-agent: *\nAllow: /\n\nSitemap: "));
+        let custom = "User-agent: *\nDisallow: /api\nsitemap: https://example.com/sitemap.xml";
+        assert_eq!(render_robots(Some(custom)), custom);
+    }
+}